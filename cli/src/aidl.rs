@@ -0,0 +1,383 @@
+//! Transaction codes of `IPackageManager` from its AIDL. A method gets
+//! `FIRST_CALL_TRANSACTION + n` for the `n`th method of the interface, or its `= N`.
+//! Generates pm_codes.rs and zygisk/jni/pm_codes.hpp from the files of aidl/.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// `IBinder.FIRST_CALL_TRANSACTION`
+const FIRST_CALL_TRANSACTION: u32 = 1;
+/// The methods with a package name as their first argument the tables have the codes of
+pub const METHODS: [&str; 4] = [
+    "isPackageAvailable",
+    "getPackageInfo",
+    "getPackageUid",
+    "getPackageGids",
+];
+/// aidl/IPackageManager-<sdk>.aidl
+const PREFIX: &str = "IPackageManager-";
+const SUFFIX: &str = ".aidl";
+const HEADER: &str = "Generated by `detach aidl-gen` from aidl/, do not edit";
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    /// identifiers, keywords, qualified names and numbers
+    Word(&'a str),
+    /// string and char literals, with their quotes
+    Literal(&'a str),
+    Punct(char),
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token<'_>>, String> {
+    let mut tokens = Vec::new();
+    let bytes = src.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &src[i..];
+        let c = rest.chars().next().unwrap();
+        if c.is_whitespace() {
+            i += c.len_utf8();
+        } else if rest.starts_with("//") {
+            i += rest.find('\n').unwrap_or(rest.len());
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            let end = comment.find("*/").ok_or("unterminated comment")?;
+            i += 2 + end + 2;
+        } else if c == '"' || c == '\'' {
+            let mut end = 1;
+            loop {
+                match bytes.get(i + end) {
+                    None => return Err("unterminated literal".to_string()),
+                    Some(b'\\') => end += 2,
+                    Some(&b) if b == c as u8 => break,
+                    Some(_) => end += 1,
+                }
+            }
+            tokens.push(Token::Literal(&src[i..=i + end]));
+            i += end + 1;
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Word(&rest[..end]));
+            i += end;
+        } else {
+            tokens.push(Token::Punct(c));
+            i += c.len_utf8();
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    i: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.i)
+    }
+
+    fn next(&mut self) -> Result<&Token<'a>, String> {
+        let t = self.tokens.get(self.i).ok_or("unexpected end of file")?;
+        self.i += 1;
+        Ok(t)
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        match self.next()? {
+            Token::Punct(p) if *p == c => Ok(()),
+            t => Err(format!("expected '{c}', found {t:?}")),
+        }
+    }
+
+    fn word(&mut self) -> Result<&'a str, String> {
+        match self.next()? {
+            Token::Word(w) => Ok(w),
+            t => Err(format!("expected a name, found {t:?}")),
+        }
+    }
+
+    /// Past the `close` matching the `open` at the cursor
+    fn skip_balanced(&mut self, open: char, close: char) -> Result<(), String> {
+        self.expect(open)?;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next()? {
+                Token::Punct(c) if *c == open => depth += 1,
+                Token::Punct(c) if *c == close => depth -= 1,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// `@Name` or `@Name(...)`
+    fn skip_annotation(&mut self) -> Result<(), String> {
+        self.expect('@')?;
+        self.word()?;
+        if self.peek() == Some(&Token::Punct('(')) {
+            self.skip_balanced('(', ')')?;
+        }
+        Ok(())
+    }
+
+    /// Past the `;` of a statement, or the block of a declaration
+    fn skip_declaration(&mut self) -> Result<(), String> {
+        loop {
+            match self.peek() {
+                Some(Token::Punct(';')) => {
+                    self.i += 1;
+                    return Ok(());
+                }
+                Some(Token::Punct('{')) => return self.skip_balanced('{', '}'),
+                _ => {
+                    self.next()?;
+                }
+            }
+        }
+    }
+
+    /// The methods of the interface at the cursor and their `= N`, the cursor is past its `}`
+    fn interface(&mut self) -> Result<Vec<(&'a str, Option<u32>)>, String> {
+        let mut methods = Vec::new();
+        self.expect('{')?;
+        loop {
+            match self.peek().ok_or("unterminated interface")? {
+                Token::Punct('}') => {
+                    self.i += 1;
+                    return Ok(methods);
+                }
+                Token::Punct('@') => self.skip_annotation()?,
+                Token::Word("oneway") => self.i += 1,
+                // constants and nested types
+                Token::Word("const" | "parcelable" | "enum" | "union" | "interface") => {
+                    self.skip_declaration()?
+                }
+                _ => methods.push(self.method()?),
+            }
+        }
+    }
+
+    /// `Type name(args) [= N];`, the type may be generic or an array
+    fn method(&mut self) -> Result<(&'a str, Option<u32>), String> {
+        let mut name = None;
+        while self.peek() != Some(&Token::Punct('(')) {
+            name = match self.next()? {
+                Token::Word(w) => Some(*w),
+                Token::Punct('<' | '>' | ',' | '[' | ']') => None,
+                t => return Err(format!("unexpected {t:?} in a method declaration")),
+            };
+        }
+        let name = name.ok_or("a method without a name")?;
+        self.skip_balanced('(', ')')?;
+        let mut code = None;
+        if self.peek() == Some(&Token::Punct('=')) {
+            self.i += 1;
+            let n = self.word()?;
+            code = Some(
+                n.parse()
+                    .map_err(|_| format!("invalid transaction code of {name}: '{n}'"))?,
+            );
+        }
+        self.expect(';')?;
+        Ok((name, code))
+    }
+}
+
+/// The methods of the interface of `src` with their transaction codes, in declaration order
+pub fn transaction_codes(src: &str) -> Result<Vec<(String, u32)>, String> {
+    let mut p = Parser {
+        tokens: tokenize(src)?,
+        i: 0,
+    };
+    while let Some(t) = p.peek() {
+        match t {
+            Token::Punct('@') => p.skip_annotation()?,
+            Token::Word("oneway") => p.i += 1,
+            Token::Word("interface") => {
+                p.i += 1;
+                p.word()?;
+                let methods = p.interface()?;
+                let explicit = methods.iter().filter(|(_, c)| c.is_some()).count();
+                if explicit != 0 && explicit != methods.len() {
+                    return Err("either every method or none has a transaction code".to_string());
+                }
+                return Ok(methods
+                    .into_iter()
+                    .zip(FIRST_CALL_TRANSACTION..)
+                    .map(|((name, code), n)| (name.to_string(), code.unwrap_or(n)))
+                    .collect());
+            }
+            // package, imports and top level parcelables
+            _ => p.skip_declaration()?,
+        }
+    }
+    Err("no interface".to_string())
+}
+
+/// `(sdk, codes of METHODS)` of every aidl/IPackageManager-<sdk>.aidl of `dir`, by SDK
+pub fn read_tables(dir: &Path) -> io::Result<Vec<(u32, [u32; METHODS.len()])>> {
+    let mut tables = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let Some(sdk) = file_name
+            .strip_prefix(PREFIX)
+            .and_then(|n| n.strip_suffix(SUFFIX))
+        else {
+            continue;
+        };
+        let invalid = |e: String| io::Error::other(format!("{}: {e}", path.display()));
+        let sdk = sdk
+            .parse()
+            .map_err(|_| invalid(format!("'{sdk}' is not an SDK level")))?;
+        let codes = transaction_codes(&fs::read_to_string(&path)?).map_err(invalid)?;
+        let mut table = [0; METHODS.len()];
+        for (code, method) in table.iter_mut().zip(METHODS) {
+            *code = codes
+                .iter()
+                .find(|(name, _)| name == method)
+                .ok_or_else(|| invalid(format!("no {method}")))?
+                .1;
+        }
+        tables.push((sdk, table));
+    }
+    if tables.is_empty() {
+        return Err(io::Error::other(format!(
+            "no {PREFIX}<sdk>{SUFFIX} in {}",
+            dir.display()
+        )));
+    }
+    tables.sort_unstable_by_key(|(sdk, _)| *sdk);
+    Ok(tables)
+}
+
+/// pm_codes.rs, formatted as rustfmt would
+pub fn rust_table(tables: &[(u32, [u32; METHODS.len()])]) -> String {
+    let mut out = format!("//! {HEADER}\n\n");
+    writeln!(out, "pub const METHODS: [&str; {}] = [", METHODS.len()).unwrap();
+    for method in METHODS {
+        writeln!(out, "    \"{method}\",").unwrap();
+    }
+    out.push_str("];\n\n/// `(sdk, codes of METHODS)`, by SDK\n");
+    writeln!(
+        out,
+        "pub const CODES: [(u32, [u32; {}]); {}] = [",
+        METHODS.len(),
+        tables.len()
+    )
+    .unwrap();
+    for (sdk, codes) in tables {
+        let codes: Vec<String> = codes.iter().map(u32::to_string).collect();
+        writeln!(out, "    ({sdk}, [{}]),", codes.join(", ")).unwrap();
+    }
+    out.push_str("];\n");
+    out
+}
+
+/// zygisk/jni/pm_codes.hpp
+pub fn c_header(tables: &[(u32, [u32; METHODS.len()])]) -> String {
+    let mut out = format!("// {HEADER}\n\n#pragma once\n\n#include <stdint.h>\n\n");
+    out.push_str("struct PmCodes {\n    uint32_t sdk;\n");
+    for method in METHODS {
+        writeln!(out, "    uint32_t {method};").unwrap();
+    }
+    out.push_str("};\n\n// by SDK\nstatic const struct PmCodes PM_CODES[] = {\n");
+    for (sdk, codes) in tables {
+        let codes: Vec<String> = codes.iter().map(u32::to_string).collect();
+        writeln!(out, "    {{{sdk}, {}}},", codes.join(", ")).unwrap();
+    }
+    out.push_str("};\n");
+    out
+}
+
+/// Regenerates the tables from the AIDL files of `dir`
+pub fn generate(dir: &str, rs: &str, hpp: &str) -> io::Result<usize> {
+    let tables = read_tables(Path::new(dir))?;
+    fs::write(rs, rust_table(&tables))?;
+    fs::write(hpp, c_header(&tables))?;
+    Ok(tables.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(src: &str) -> Vec<(String, u32)> {
+        transaction_codes(src).unwrap()
+    }
+
+    fn named(codes: &[(&str, u32)]) -> Vec<(String, u32)> {
+        codes.iter().map(|(n, c)| (n.to_string(), *c)).collect()
+    }
+
+    #[test]
+    fn skips_comments_annotations_and_oneway() {
+        let src = r#"
+            package android.content.pm;
+            import android.content.pm.PackageInfo;
+            /** {@hide}; not a method(); */
+            @JavaDerive(toString = true)
+            interface IFoo {
+                // void commented(int a);
+                @UnsupportedAppUsage(maxTargetSdk = 30, trackingBug = 170729553)
+                void first(String name /* ; */, int userId);
+                @EnforcePermission("MANAGE_USERS") oneway void second(in String[] names);
+                Map<String, List<String>> third(@nullable String s, char c = ';');
+                /* boolean fourth(); */ int[] fourth(String s);
+            }
+        "#;
+        assert_eq!(
+            codes(src),
+            named(&[("first", 1), ("second", 2), ("third", 3), ("fourth", 4)])
+        );
+    }
+
+    #[test]
+    fn skips_constants_and_nested_types() {
+        let src = "
+            parcelable Top;
+            oneway interface IFoo {
+                const int FLAG = 1 << 2;
+                const String NAME = \"a;b\";
+                parcelable Nested {
+                    int a;
+                    @nullable String b;
+                    parcelable Deeper { long c; }
+                }
+                enum Kind { A = 1, B, }
+                union U { int i; String s; }
+                void first(in Nested n);
+                interface ICallback { void notFoo(); }
+                Nested second();
+            }
+        ";
+        assert_eq!(codes(src), named(&[("first", 1), ("second", 2)]));
+    }
+
+    #[test]
+    fn takes_explicit_codes() {
+        let src = "interface IFoo { void a() = 10; oneway void b(int x) = 3; }";
+        assert_eq!(codes(src), named(&[("a", 10), ("b", 3)]));
+        assert!(transaction_codes("interface IFoo { void a() = 10; void b(); }").is_err());
+        assert!(transaction_codes("interface IFoo { void a(); ").is_err());
+        assert!(transaction_codes("/* interface IFoo { void a(); }").is_err());
+        assert!(transaction_codes("parcelable Foo { int a; }").is_err());
+    }
+
+    /// The checked in tables are the ones of the vendored AIDL files
+    #[test]
+    fn generated_tables_are_up_to_date() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let tables = read_tables(&root.join("../aidl")).unwrap();
+        assert_eq!(rust_table(&tables), include_str!("pm_codes.rs"));
+        assert_eq!(
+            c_header(&tables),
+            include_str!("../../zygisk/jni/pm_codes.hpp")
+        );
+        assert!(tables.iter().all(|(_, codes)| codes[1] == 3));
+    }
+}
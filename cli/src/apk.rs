@@ -0,0 +1,673 @@
+use flate2::read::DeflateDecoder;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+const MANIFEST: &str = "AndroidManifest.xml";
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn u16_at(b: &[u8], off: usize) -> io::Result<u16> {
+    b.get(off..off + 2)
+        .map(|s| u16::from_le_bytes([s[0], s[1]]))
+        .ok_or_else(|| invalid("unexpected end of data"))
+}
+
+fn u32_at(b: &[u8], off: usize) -> io::Result<u32> {
+    b.get(off..off + 4)
+        .map(|s| u32::from_le_bytes([s[0], s[1], s[2], s[3]]))
+        .ok_or_else(|| invalid("unexpected end of data"))
+}
+
+/// Package names of the APK, split APK bundle (.apks/.xapk) or directory of those at `path`.
+pub fn package_names(path: &Path) -> io::Result<Vec<String>> {
+    let mut pkgs = Vec::new();
+    if path.is_dir() {
+        let mut entries = fs::read_dir(path)?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+        for p in entries {
+            let is_apk = p.extension().is_some_and(|ext| {
+                ["apk", "apks", "xapk"]
+                    .iter()
+                    .any(|e| ext.eq_ignore_ascii_case(e))
+            });
+            if is_apk && p.is_file() {
+                let pkg = File::open(&p)
+                    .and_then(package_name)
+                    .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", p.display())))?;
+                if !pkgs.contains(&pkg) {
+                    pkgs.push(pkg);
+                }
+            }
+        }
+    } else {
+        pkgs.push(package_name(File::open(path)?)?);
+    }
+    Ok(pkgs)
+}
+
+fn package_name<R: Read + Seek>(r: R) -> io::Result<String> {
+    let mut zip = Zip::new(r)?;
+    if let Some(manifest) = zip.entries.iter().find(|e| e.name == MANIFEST).cloned() {
+        return manifest_package(&zip.read(&manifest)?);
+    }
+    // split apk bundle, every split carries the same package attribute
+    let apks: Vec<ZipEntry> = zip
+        .entries
+        .iter()
+        .filter(|e| e.name.ends_with(".apk"))
+        .cloned()
+        .collect();
+    for apk in &apks {
+        let inner = zip.read(apk)?;
+        if let Ok(pkg) = package_name(Cursor::new(inner)) {
+            return Ok(pkg);
+        }
+    }
+    Err(invalid("no AndroidManifest.xml or APKs inside the archive"))
+}
+
+/// SHA-256 of the first signer's certificate from the APK Signature Scheme v3 (or v2) block,
+/// the same digest `apksigner verify --print-certs` shows. APKs with only a v1 (JAR)
+/// signature fall back to the certificate of `META-INF/*.RSA|DSA|EC`.
+pub fn signer_cert_digest(path: &Path) -> io::Result<[u8; 32]> {
+    cert_digest(File::open(path)?)
+}
+
+fn cert_digest<R: Read + Seek>(r: R) -> io::Result<[u8; 32]> {
+    const MAGIC: &[u8; 16] = b"APK Sig Block 42";
+    const V2_ID: u32 = 0x7109871a;
+    const V3_ID: u32 = 0xf05368c0;
+
+    let mut zip = Zip::new(r)?;
+    if zip.cd_offset < 24 {
+        return v1_cert_digest(&mut zip);
+    }
+    let r = &mut zip.r;
+    let mut footer = [0u8; 24];
+    r.seek(SeekFrom::Start(zip.cd_offset - 24))?;
+    r.read_exact(&mut footer)?;
+    if &footer[8..] != MAGIC {
+        return v1_cert_digest(&mut zip);
+    }
+    let block_size = u64::from_le_bytes(footer[..8].try_into().unwrap());
+    // the size field is repeated at the start and is not counted by itself, the block
+    // starts 8 bytes before `cd_offset - block_size`
+    if block_size < 24 || block_size.checked_add(8).is_none_or(|n| n > zip.cd_offset) {
+        return Err(invalid("corrupted APK signing block"));
+    }
+    let pairs_len = block_size - 24;
+    r.seek(SeekFrom::Start(zip.cd_offset - block_size))?;
+    let mut pairs = vec![0; pairs_len as usize];
+    r.read_exact(&mut pairs)?;
+
+    let (mut v2, mut v3) = (None, None);
+    let mut i = 0;
+    while i + 12 <= pairs.len() {
+        let len = u64::from_le_bytes(pairs[i..i + 8].try_into().unwrap());
+        let end = usize::try_from(len)
+            .ok()
+            .and_then(|len| (i + 8).checked_add(len))
+            .ok_or_else(|| invalid("corrupted APK signing block"))?;
+        let value = pairs
+            .get(i + 12..end)
+            .ok_or_else(|| invalid("corrupted APK signing block"))?;
+        match u32_at(&pairs, i + 8)? {
+            V2_ID => v2 = Some(value),
+            V3_ID => v3 = Some(value),
+            _ => {}
+        }
+        i = end;
+    }
+    let Some(scheme) = v3.or(v2) else {
+        return v1_cert_digest(&mut zip);
+    };
+    // signers -> signer -> signed data -> (digests, certificates) -> certificate
+    let signer = len_prefixed(len_prefixed(scheme, 0)?, 0)?;
+    let signed_data = len_prefixed(signer, 0)?;
+    let digests = len_prefixed(signed_data, 0)?;
+    let certs = len_prefixed(signed_data, 4 + digests.len())?;
+    let cert = len_prefixed(certs, 0)?;
+    Ok(Sha256::digest(cert).into())
+}
+
+/// The first certificate of the PKCS#7 signature of a JAR signed APK,
+/// apksigner and jarsigner only put the signer's in there
+fn v1_cert_digest<R: Read + Seek>(zip: &mut Zip<R>) -> io::Result<[u8; 32]> {
+    const SEQUENCE: u8 = 0x30;
+    const SET: u8 = 0x31;
+    const CONTEXT_0: u8 = 0xa0;
+
+    let entry = zip
+        .entries
+        .iter()
+        .find(|e| {
+            let name = e.name.to_ascii_uppercase();
+            name.starts_with("META-INF/")
+                && [".RSA", ".DSA", ".EC"]
+                    .iter()
+                    .any(|ext| name.ends_with(ext))
+        })
+        .cloned()
+        .ok_or_else(|| invalid("no v1, v2 or v3 signature"))?;
+    let p7 = zip.read(&entry)?;
+    // ContentInfo -> [0] SignedData -> (version, digestAlgorithms, contentInfo, [0] certificates)
+    let (content_info, _) = der(&p7, SEQUENCE)?;
+    let (_, content_info) = der(content_info, 0x06)?;
+    let (signed_data, _) = der(content_info, CONTEXT_0)?;
+    let (signed_data, _) = der(signed_data, SEQUENCE)?;
+    let (_, rest) = der(signed_data, 0x02)?;
+    let (_, rest) = der(rest, SET)?;
+    let (_, rest) = der(rest, SEQUENCE)?;
+    let (certs, _) = der(rest, CONTEXT_0)?;
+    let (_, after) = der(certs, SEQUENCE)?;
+    Ok(Sha256::digest(&certs[..certs.len() - after.len()]).into())
+}
+
+/// The contents of the DER element `tag` at the start of `b` and what follows it
+fn der(b: &[u8], tag: u8) -> io::Result<(&[u8], &[u8])> {
+    let corrupted = || invalid("corrupted v1 signature");
+    let [t, l, b @ ..] = b else {
+        return Err(corrupted());
+    };
+    if *t != tag {
+        return Err(corrupted());
+    }
+    let (len, b) = match *l {
+        l @ 0..=0x7f => (l as usize, b),
+        // long form, indefinite lengths are not DER
+        l @ 0x81..=0x84 => {
+            let n = (l & 0x7f) as usize;
+            let len = b
+                .get(..n)
+                .ok_or_else(corrupted)?
+                .iter()
+                .fold(0, |len, &d| len << 8 | d as usize);
+            (len, &b[n..])
+        }
+        _ => return Err(corrupted()),
+    };
+    if len > b.len() {
+        return Err(corrupted());
+    }
+    Ok(b.split_at(len))
+}
+
+fn len_prefixed(b: &[u8], off: usize) -> io::Result<&[u8]> {
+    let len = u32_at(b, off)? as usize;
+    (off + 4)
+        .checked_add(len)
+        .and_then(|end| b.get(off + 4..end))
+        .ok_or_else(|| invalid("corrupted APK signing block"))
+}
+
+#[derive(Clone)]
+struct ZipEntry {
+    name: String,
+    method: u16,
+    compressed_size: u32,
+    local_header: u32,
+}
+
+struct Zip<R> {
+    r: R,
+    entries: Vec<ZipEntry>,
+    cd_offset: u64,
+}
+
+impl<R: Read + Seek> Zip<R> {
+    fn new(mut r: R) -> io::Result<Self> {
+        const EOCD_SIG: u32 = 0x06054b50;
+        const CD_SIG: u32 = 0x02014b50;
+        const EOCD_LEN: u64 = 22;
+
+        let len = r.seek(SeekFrom::End(0))?;
+        if len < EOCD_LEN {
+            return Err(invalid("not a zip file"));
+        }
+        // the comment after the eocd record can be at most u16::MAX long
+        let tail_len = len.min(EOCD_LEN + u16::MAX as u64);
+        r.seek(SeekFrom::Start(len - tail_len))?;
+        let mut tail = vec![0; tail_len as usize];
+        r.read_exact(&mut tail)?;
+        let eocd = (0..=tail.len() - EOCD_LEN as usize)
+            .rev()
+            .find(|&i| u32_at(&tail, i).is_ok_and(|sig| sig == EOCD_SIG))
+            .ok_or_else(|| invalid("not a zip file"))?;
+        let count = u16_at(&tail, eocd + 10)?;
+        let cd_size = u32_at(&tail, eocd + 12)?;
+        let cd_off = u32_at(&tail, eocd + 16)?;
+        if cd_off == u32::MAX || count == u16::MAX {
+            return Err(invalid("zip64 archives are not supported"));
+        }
+
+        r.seek(SeekFrom::Start(cd_off as u64))?;
+        let mut cd = vec![0; cd_size as usize];
+        r.read_exact(&mut cd)?;
+        let mut entries = Vec::with_capacity(count as usize);
+        let mut i = 0;
+        for _ in 0..count {
+            if u32_at(&cd, i)? != CD_SIG {
+                return Err(invalid("corrupted zip central directory"));
+            }
+            let name_len = u16_at(&cd, i + 28)? as usize;
+            let extra_len = u16_at(&cd, i + 30)? as usize;
+            let comment_len = u16_at(&cd, i + 32)? as usize;
+            let name = cd
+                .get(i + 46..i + 46 + name_len)
+                .ok_or_else(|| invalid("corrupted zip central directory"))?;
+            entries.push(ZipEntry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                method: u16_at(&cd, i + 10)?,
+                compressed_size: u32_at(&cd, i + 20)?,
+                local_header: u32_at(&cd, i + 42)?,
+            });
+            i += 46 + name_len + extra_len + comment_len;
+        }
+        Ok(Self {
+            r,
+            entries,
+            cd_offset: cd_off as u64,
+        })
+    }
+
+    fn read(&mut self, entry: &ZipEntry) -> io::Result<Vec<u8>> {
+        const LOCAL_SIG: u32 = 0x04034b50;
+        let mut header = [0u8; 30];
+        self.r.seek(SeekFrom::Start(entry.local_header as u64))?;
+        self.r.read_exact(&mut header)?;
+        if u32_at(&header, 0)? != LOCAL_SIG {
+            return Err(invalid("corrupted zip local header"));
+        }
+        let skip = u16_at(&header, 26)? as i64 + u16_at(&header, 28)? as i64;
+        self.r.seek(SeekFrom::Current(skip))?;
+        let mut data = (&mut self.r).take(entry.compressed_size as u64);
+        let mut out = Vec::new();
+        match entry.method {
+            0 => data.read_to_end(&mut out)?,
+            8 => DeflateDecoder::new(data).read_to_end(&mut out)?,
+            m => return Err(invalid(&format!("unsupported zip compression method {m}"))),
+        };
+        Ok(out)
+    }
+}
+
+/// Pulls the `package` attribute of `<manifest>` out of a binary AndroidManifest.xml
+fn manifest_package(axml: &[u8]) -> io::Result<String> {
+    const RES_XML_TYPE: u16 = 0x0003;
+    const RES_STRING_POOL_TYPE: u16 = 0x0001;
+    const RES_XML_START_ELEMENT_TYPE: u16 = 0x0102;
+    const TYPE_STRING: u8 = 0x03;
+    const NO_ENTRY: u32 = u32::MAX;
+
+    if u16_at(axml, 0)? != RES_XML_TYPE {
+        return Err(invalid("not a binary xml"));
+    }
+    let mut i = u16_at(axml, 2)? as usize;
+    let mut strings = None;
+    while i < axml.len() {
+        let chunk_type = u16_at(axml, i)?;
+        let header_size = u16_at(axml, i + 2)? as usize;
+        let chunk_size = u32_at(axml, i + 4)? as usize;
+        if chunk_size < 8 {
+            return Err(invalid("corrupted binary xml chunk"));
+        }
+        let chunk = axml
+            .get(i..i + chunk_size)
+            .ok_or_else(|| invalid("corrupted binary xml chunk"))?;
+        match chunk_type {
+            RES_STRING_POOL_TYPE => strings = Some(StringPool::new(chunk)?),
+            RES_XML_START_ELEMENT_TYPE => {
+                let strings = strings
+                    .as_ref()
+                    .ok_or_else(|| invalid("string pool missing"))?;
+                let ext = header_size;
+                if strings.get(u32_at(chunk, ext + 4)?)? != "manifest" {
+                    return Err(invalid("first element is not <manifest>"));
+                }
+                let attr_start = u16_at(chunk, ext + 8)? as usize;
+                let attr_size = u16_at(chunk, ext + 10)? as usize;
+                let attr_count = u16_at(chunk, ext + 12)? as usize;
+                for n in 0..attr_count {
+                    let attr = ext + attr_start + n * attr_size;
+                    if strings.get(u32_at(chunk, attr + 4)?)? != "package" {
+                        continue;
+                    }
+                    let raw = u32_at(chunk, attr + 8)?;
+                    let data_type = *chunk
+                        .get(attr + 15)
+                        .ok_or_else(|| invalid("unexpected end of data"))?;
+                    let idx = if raw != NO_ENTRY {
+                        raw
+                    } else if data_type == TYPE_STRING {
+                        u32_at(chunk, attr + 16)?
+                    } else {
+                        break;
+                    };
+                    return strings.get(idx);
+                }
+                return Err(invalid("<manifest> has no package attribute"));
+            }
+            _ => {}
+        }
+        i += chunk_size;
+    }
+    Err(invalid("no <manifest> element"))
+}
+
+struct StringPool<'a> {
+    chunk: &'a [u8],
+    count: u32,
+    utf8: bool,
+    strings_start: usize,
+    offsets_start: usize,
+}
+
+impl<'a> StringPool<'a> {
+    fn new(chunk: &'a [u8]) -> io::Result<Self> {
+        const UTF8_FLAG: u32 = 1 << 8;
+        Ok(Self {
+            chunk,
+            count: u32_at(chunk, 8)?,
+            utf8: u32_at(chunk, 16)? & UTF8_FLAG != 0,
+            strings_start: u32_at(chunk, 20)? as usize,
+            offsets_start: u16_at(chunk, 2)? as usize,
+        })
+    }
+
+    fn get(&self, idx: u32) -> io::Result<String> {
+        if idx >= self.count {
+            return Err(invalid("string index out of bounds"));
+        }
+        let mut i = self.strings_start
+            + u32_at(self.chunk, self.offsets_start + idx as usize * 4)? as usize;
+        if self.utf8 {
+            // utf-16 length first, then the utf-8 length, both 1 or 2 bytes
+            let (_, n) = self.utf8_len(i)?;
+            i += n;
+            let (len, n) = self.utf8_len(i)?;
+            i += n;
+            let bytes = self
+                .chunk
+                .get(i..i + len)
+                .ok_or_else(|| invalid("unexpected end of data"))?;
+            String::from_utf8(bytes.to_vec()).map_err(|_| invalid("invalid utf-8 string"))
+        } else {
+            let mut len = u16_at(self.chunk, i)? as usize;
+            i += 2;
+            if len & 0x8000 != 0 {
+                len = ((len & 0x7fff) << 16) | u16_at(self.chunk, i)? as usize;
+                i += 2;
+            }
+            let units = (0..len)
+                .map(|n| u16_at(self.chunk, i + n * 2))
+                .collect::<io::Result<Vec<u16>>>()?;
+            String::from_utf16(&units).map_err(|_| invalid("invalid utf-16 string"))
+        }
+    }
+
+    fn utf8_len(&self, i: usize) -> io::Result<(usize, usize)> {
+        let b0 = *self
+            .chunk
+            .get(i)
+            .ok_or_else(|| invalid("unexpected end of data"))? as usize;
+        if b0 & 0x80 != 0 {
+            let b1 = *self
+                .chunk
+                .get(i + 1)
+                .ok_or_else(|| invalid("unexpected end of data"))? as usize;
+            Ok((((b0 & 0x7f) << 8) | b1, 2))
+        } else {
+            Ok((b0, 1))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A zip of stored `entries`, with `signing_block` between the data and the central directory
+    fn zip(entries: &[(&str, &[u8])], signing_block: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut cd = Vec::new();
+        for (name, data) in entries {
+            let local = out.len() as u32;
+            out.extend(0x04034b50u32.to_le_bytes());
+            out.extend([0; 14]);
+            out.extend((data.len() as u32).to_le_bytes());
+            out.extend((data.len() as u32).to_le_bytes());
+            out.extend((name.len() as u16).to_le_bytes());
+            out.extend(0u16.to_le_bytes());
+            out.extend(name.as_bytes());
+            out.extend(*data);
+
+            cd.extend(0x02014b50u32.to_le_bytes());
+            cd.extend([0; 6]);
+            // stored
+            cd.extend(0u16.to_le_bytes());
+            cd.extend([0; 8]);
+            cd.extend((data.len() as u32).to_le_bytes());
+            cd.extend((data.len() as u32).to_le_bytes());
+            cd.extend((name.len() as u16).to_le_bytes());
+            cd.extend([0; 12]);
+            cd.extend(local.to_le_bytes());
+            cd.extend(name.as_bytes());
+        }
+        out.extend(signing_block);
+        let cd_off = out.len() as u32;
+        out.extend(&cd);
+        out.extend(0x06054b50u32.to_le_bytes());
+        out.extend([0; 4]);
+        out.extend((entries.len() as u16).to_le_bytes());
+        out.extend((entries.len() as u16).to_le_bytes());
+        out.extend((cd.len() as u32).to_le_bytes());
+        out.extend(cd_off.to_le_bytes());
+        out.extend(0u16.to_le_bytes());
+        out
+    }
+
+    fn lp(b: &[u8]) -> Vec<u8> {
+        let mut out = (b.len() as u32).to_le_bytes().to_vec();
+        out.extend(b);
+        out
+    }
+
+    /// An APK signing block with one v2 signer of `cert`
+    fn signing_block(cert: &[u8]) -> Vec<u8> {
+        let digests = lp(&[]);
+        let signed_data = [digests, lp(&lp(cert))].concat();
+        let signer = lp(&signed_data);
+        let scheme = lp(&lp(&signer));
+        let mut pairs = ((4 + scheme.len()) as u64).to_le_bytes().to_vec();
+        pairs.extend(0x7109871au32.to_le_bytes());
+        pairs.extend(&scheme);
+        with_size(&pairs, pairs.len() as u64 + 24)
+    }
+
+    fn with_size(pairs: &[u8], block_size: u64) -> Vec<u8> {
+        let mut block = block_size.to_le_bytes().to_vec();
+        block.extend(pairs);
+        block.extend(block_size.to_le_bytes());
+        block.extend(b"APK Sig Block 42");
+        block
+    }
+
+    /// A binary AndroidManifest.xml of `<manifest package="pkg">`
+    fn axml(pkg: &str) -> Vec<u8> {
+        let strings = ["package", "manifest", pkg];
+        let mut pool_data = Vec::new();
+        let mut offsets = Vec::new();
+        for s in strings {
+            offsets.extend((pool_data.len() as u32).to_le_bytes());
+            pool_data.extend([s.len() as u8, s.len() as u8]);
+            pool_data.extend(s.as_bytes());
+            pool_data.push(0);
+        }
+        while pool_data.len() % 4 != 0 {
+            pool_data.push(0);
+        }
+        let mut pool = 0x0001u16.to_le_bytes().to_vec();
+        pool.extend(28u16.to_le_bytes());
+        pool.extend(((28 + offsets.len() + pool_data.len()) as u32).to_le_bytes());
+        pool.extend((strings.len() as u32).to_le_bytes());
+        pool.extend(0u32.to_le_bytes());
+        // utf-8
+        pool.extend((1u32 << 8).to_le_bytes());
+        pool.extend(((28 + offsets.len()) as u32).to_le_bytes());
+        pool.extend(0u32.to_le_bytes());
+        pool.extend(&offsets);
+        pool.extend(&pool_data);
+
+        let mut attr = u32::MAX.to_le_bytes().to_vec();
+        attr.extend(0u32.to_le_bytes());
+        attr.extend(2u32.to_le_bytes());
+        attr.extend(8u16.to_le_bytes());
+        attr.extend([0, 0x03]);
+        attr.extend(2u32.to_le_bytes());
+        let mut element = 0x0102u16.to_le_bytes().to_vec();
+        element.extend(16u16.to_le_bytes());
+        element.extend(((16 + 20 + attr.len()) as u32).to_le_bytes());
+        element.extend([0; 8]);
+        element.extend(u32::MAX.to_le_bytes());
+        element.extend(1u32.to_le_bytes());
+        element.extend(20u16.to_le_bytes());
+        element.extend(20u16.to_le_bytes());
+        element.extend(1u16.to_le_bytes());
+        element.extend([0; 6]);
+        element.extend(&attr);
+
+        let mut xml = 0x0003u16.to_le_bytes().to_vec();
+        xml.extend(8u16.to_le_bytes());
+        xml.extend(((8 + pool.len() + element.len()) as u32).to_le_bytes());
+        xml.extend(&pool);
+        xml.extend(&element);
+        xml
+    }
+
+    #[test]
+    fn reads_the_manifest_package() {
+        let apk = zip(
+            &[("classes.dex", b"dex"), (MANIFEST, &axml("com.app1"))],
+            &[],
+        );
+        assert_eq!(package_name(Cursor::new(&apk)).unwrap(), "com.app1");
+        // a split apk bundle
+        let apks = zip(&[("base.apk", &apk)], &[]);
+        assert_eq!(package_name(Cursor::new(apks)).unwrap(), "com.app1");
+        assert!(manifest_package(&axml("com.app1")[..60]).is_err());
+        assert!(manifest_package(b"<manifest/>").is_err());
+    }
+
+    #[test]
+    fn rejects_a_bad_eocd() {
+        let apk = zip(&[(MANIFEST, &axml("com.app1"))], &[]);
+        assert!(Zip::new(Cursor::new(&apk[..apk.len() - 4])).is_err());
+        let mut bad = apk.clone();
+        let eocd = bad.len() - 22;
+        bad[eocd] = b'X';
+        assert!(Zip::new(Cursor::new(bad)).is_err());
+        // central directory past the end
+        let mut bad = apk;
+        let at = bad.len() - 6;
+        bad[at..at + 4].copy_from_slice(&u32::MAX.wrapping_sub(1).to_le_bytes());
+        assert!(Zip::new(Cursor::new(bad)).is_err());
+        assert!(Zip::new(Cursor::new(b"PK")).is_err());
+    }
+
+    #[test]
+    fn digests_the_signer_certificate() {
+        let cert = b"not really DER but any bytes do";
+        let apk = zip(&[(MANIFEST, &axml("com.app1"))], &signing_block(cert));
+        let digest: [u8; 32] = Sha256::digest(cert).into();
+        assert_eq!(cert_digest(Cursor::new(apk)).unwrap(), digest);
+
+        let unsigned = zip(&[(MANIFEST, &axml("com.app1"))], &[]);
+        assert!(cert_digest(Cursor::new(unsigned)).is_err());
+    }
+
+    fn tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        match contents.len() {
+            len @ 0..0x80 => out.push(len as u8),
+            len => {
+                out.push(0x82);
+                out.extend((len as u16).to_be_bytes());
+            }
+        }
+        out.extend(contents);
+        out
+    }
+
+    /// The PKCS#7 of a JAR signature carrying `cert`
+    fn pkcs7(cert: &[u8]) -> Vec<u8> {
+        let signed_data = [
+            tlv(0x02, &[1]),
+            tlv(0x31, &[]),
+            tlv(0x30, &tlv(0x06, &[0x2a])),
+            tlv(0xa0, cert),
+            tlv(0x31, &[]),
+        ]
+        .concat();
+        let content = [tlv(0x06, &[0x2a]), tlv(0xa0, &tlv(0x30, &signed_data))].concat();
+        tlv(0x30, &content)
+    }
+
+    #[test]
+    fn falls_back_to_the_v1_signature() {
+        let cert = tlv(0x30, &[7; 200]);
+        let rsa = pkcs7(&cert);
+        let digest: [u8; 32] = Sha256::digest(&cert).into();
+        let entries: &[(&str, &[u8])] = &[
+            (MANIFEST, &axml("com.app1")),
+            ("META-INF/MANIFEST.MF", b"Manifest-Version: 1.0"),
+            ("META-INF/CERT.RSA", &rsa),
+        ];
+        assert_eq!(cert_digest(Cursor::new(zip(entries, &[]))).unwrap(), digest);
+        // a v2 signature comes first
+        let v2 = cert_digest(Cursor::new(zip(entries, &signing_block(b"v2")))).unwrap();
+        assert_eq!(v2, <[u8; 32]>::from(Sha256::digest(b"v2")));
+
+        for truncated in [&rsa[..rsa.len() - 1], &rsa[..3], &[]] {
+            let entries: &[(&str, &[u8])] = &[("META-INF/CERT.RSA", truncated)];
+            assert!(cert_digest(Cursor::new(zip(entries, &[]))).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_corrupted_signing_blocks() {
+        let entries: &[(&str, &[u8])] = &[("a", b"x")];
+        let data_len = zip(entries, &[]).len() - 22 - (46 + 1);
+        let block = signing_block(b"cert");
+        let pairs = &block[8..block.len() - 24];
+        let cd_offset = (data_len + block.len()) as u64;
+        for block_size in [
+            0,
+            23,
+            // reaches before the start of the file
+            cd_offset - 7,
+            cd_offset + 1,
+            cd_offset + 16,
+            u64::MAX,
+            u64::MAX - 7,
+        ] {
+            let apk = zip(entries, &with_size(pairs, block_size));
+            assert!(
+                cert_digest(Cursor::new(apk)).is_err(),
+                "block size {block_size}"
+            );
+        }
+        // a pair longer than the block
+        for len in [1u64 << 40, u64::MAX, u64::MAX - 7, 2] {
+            let mut pairs = pairs.to_vec();
+            pairs[..8].copy_from_slice(&len.to_le_bytes());
+            let apk = zip(entries, &with_size(&pairs, pairs.len() as u64 + 24));
+            assert!(cert_digest(Cursor::new(apk)).is_err(), "pair length {len}");
+        }
+    }
+}
@@ -0,0 +1,372 @@
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use sha2::{Digest, Sha256};
+
+use crate::doctor;
+use crate::stores::STORES;
+
+/// Every file of the bundle is in this directory
+const DIR: &str = "zygisk-detach-bugreport";
+
+/// Minimal ustar writer, regular files only
+struct Tar<W: Write> {
+    w: W,
+    mtime: u64,
+}
+
+impl<W: Write> Tar<W> {
+    fn add(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let path = format!("{DIR}/{name}");
+        let mut h = [0u8; 512];
+        h[..path.len()].copy_from_slice(path.as_bytes());
+        h[100..108].copy_from_slice(b"0000644\0");
+        h[108..116].copy_from_slice(b"0000000\0");
+        h[116..124].copy_from_slice(b"0000000\0");
+        h[124..136].copy_from_slice(format!("{:011o}\0", data.len()).as_bytes());
+        h[136..148].copy_from_slice(format!("{:011o}\0", self.mtime).as_bytes());
+        h[156] = b'0';
+        h[257..265].copy_from_slice(b"ustar\x0000");
+        // summed with the checksum field as spaces
+        h[148..156].fill(b' ');
+        let sum: u32 = h.iter().map(|&b| b as u32).sum();
+        h[148..156].copy_from_slice(format!("{sum:06o}\0 ").as_bytes());
+        self.w.write_all(&h)?;
+        self.w.write_all(data)?;
+        let pad = (512 - data.len() % 512) % 512;
+        self.w.write_all(&[0; 512][..pad])
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        self.w.write_all(&[0; 1024])?;
+        Ok(self.w)
+    }
+}
+
+/// Stable stand-in for a package name, the same app gets the same one across files
+fn redact(pkg: &str) -> String {
+    if STORES.iter().any(|s| s.package == pkg) {
+        return pkg.to_string();
+    }
+    let digest = Sha256::digest(pkg.as_bytes());
+    let mut token = String::from("app-");
+    for b in &digest[..4] {
+        let _ = write!(token, "{b:02x}");
+    }
+    token
+}
+
+/// `offset: hex  ascii`, 16 bytes a line
+fn hexdump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        let _ = write!(out, "{:08x}: ", i * 16);
+        for n in 0..16 {
+            match chunk.get(n) {
+                Some(b) => {
+                    let _ = write!(out, "{b:02x} ");
+                }
+                None => out.push_str("   "),
+            }
+        }
+        out.push(' ');
+        for &b in chunk {
+            out.push(if b.is_ascii_graphic() { b as char } else { '.' });
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// detach.bin with the names of the detached apps replaced by `*`, the record lengths,
+/// tags and users are left for debugging the format. Everything past a corrupted
+/// record is masked.
+fn mask_detach_bin(content: &[u8]) -> Vec<u8> {
+    let mut masked = content.to_vec();
+    let records = match crate::parse_bin_records(content) {
+        Ok(records) => records,
+        Err(offset) => {
+            let valid = crate::parse_bin_records(&content[..offset]).unwrap_or_default();
+            masked[offset..].fill(b'*');
+            valid
+        }
+    };
+    for (range, record) in records {
+        let name_start = match record.len() % 2 {
+            1 => range.start + 1,
+            _ if record.first() == Some(&crate::TAG_USER_APP) => range.start + 2 + 4,
+            // store records and unknown tags
+            _ => continue,
+        };
+        for b in &mut masked[name_start.min(range.end)..range.end] {
+            if *b != 0 {
+                *b = b'*';
+            }
+        }
+    }
+    masked
+}
+
+/// The detached apps and target stores, one a line
+fn decode_detach_bin(content: &[u8], redacted: bool) -> String {
+    let Ok(records) = crate::parse_bin_records(content) else {
+        return "corrupted, see detach.bin.hex\n".to_string();
+    };
+    let name = |pkg: &str| {
+        if redacted {
+            redact(pkg)
+        } else {
+            pkg.to_string()
+        }
+    };
+    let mut out = String::new();
+    for app in crate::get_detached_apps(content) {
+        match app.user {
+            Some(user) => writeln!(out, "{} user {user}", name(&app.name)),
+            None => writeln!(out, "{}", name(&app.name)),
+        }
+        .unwrap();
+    }
+    for (_, record) in records {
+        if record.len() % 2 == 0 && record.first() == Some(&crate::TAG_STORE) {
+            let store: String = record[1..].iter().step_by(2).map(|&b| b as char).collect();
+            writeln!(out, "store {store}").unwrap();
+        }
+    }
+    out
+}
+
+fn system_info() -> String {
+    let mut out = String::new();
+    for prop in [
+        "ro.build.fingerprint",
+        "ro.build.version.release",
+        "ro.build.version.sdk",
+        "ro.product.model",
+        "ro.product.cpu.abi",
+    ] {
+        let value = doctor::output("getprop", &[prop]).unwrap_or_default();
+        writeln!(out, "{prop}={value}").unwrap();
+    }
+    out
+}
+
+fn denylist(redacted: bool) -> String {
+    let mut out = String::new();
+    let enforced = doctor::output("magisk", &["--denylist", "status"]);
+    writeln!(
+        out,
+        "status: {}",
+        enforced.as_deref().unwrap_or("not enforced")
+    )
+    .unwrap();
+    if let Some(list) = doctor::output("magisk", &["--denylist", "ls"]) {
+        for line in list.lines() {
+            if !redacted {
+                writeln!(out, "{line}").unwrap();
+                continue;
+            }
+            // <package>|<process>, the process is often <package>:<name>
+            let (pkg, process) = line.split_once('|').unwrap_or((line, ""));
+            let process = match process.split_once(':') {
+                Some((p, sub)) => format!("{}:{sub}", redact(p)),
+                None => redact(process),
+            };
+            writeln!(out, "{}|{process}", redact(pkg)).unwrap();
+        }
+    }
+    out
+}
+
+/// Writes the bundle to `dir`, returns its path
+pub fn write(dir: &str, redacted: bool) -> io::Result<String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let path = format!("{dir}/{DIR}-{now}.tar.gz");
+    let gz = GzEncoder::new(File::create(&path)?, Compression::default());
+    let mut tar = Tar { w: gz, mtime: now };
+
+    let module_prop = fs::read(format!("{}/module.prop", doctor::MODULE_DIR))
+        .unwrap_or_else(|e| format!("{e}\n").into_bytes());
+    tar.add("module.prop", &module_prop)?;
+    tar.add("system.txt", system_info().as_bytes())?;
+
+    let mut checks = String::new();
+    for check in doctor::run(&crate::target_stores()) {
+        writeln!(checks, "{}", check.plain()).unwrap();
+    }
+    tar.add("doctor.txt", checks.as_bytes())?;
+
+    match fs::read(crate::MODULE_DETACH) {
+        Ok(content) => {
+            let dump = if redacted {
+                hexdump(&mask_detach_bin(&content))
+            } else {
+                hexdump(&content)
+            };
+            tar.add("detach.bin.hex", dump.as_bytes())?;
+            tar.add(
+                "detach.txt",
+                decode_detach_bin(&content, redacted).as_bytes(),
+            )?;
+        }
+        Err(e) => tar.add("detach.txt", format!("{e}\n").as_bytes())?,
+    }
+
+    let logcat = doctor::output(
+        "logcat",
+        &[
+            "-d",
+            "-b",
+            "main",
+            "-v",
+            "threadtime",
+            "-s",
+            crate::logs::TAG,
+        ],
+    )
+    .unwrap_or_else(|| "logcat failed".to_string());
+    tar.add("logcat.txt", format!("{logcat}\n").as_bytes())?;
+    tar.add("denylist.txt", denylist(redacted).as_bytes())?;
+
+    let packages = match crate::get_installed_apps(None) {
+        Ok(apps) => format!(
+            "{} packages installed\n",
+            crate::parse_installed_apps(&apps).len()
+        ),
+        Err(e) => format!("pm failed: {}\n", e.source),
+    };
+    tar.add("packages.txt", packages.as_bytes())?;
+
+    tar.finish()?.finish()?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    /// `(name, data)` of the entries of a tar, checking each header's checksum
+    fn untar(tar: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let octal = |field: &[u8]| {
+            let digits = std::str::from_utf8(field).unwrap();
+            u64::from_str_radix(digits.trim_end_matches(['\0', ' ']), 8).unwrap()
+        };
+        let mut entries = Vec::new();
+        let mut i = 0;
+        while tar[i..i + 512].iter().any(|&b| b != 0) {
+            let h = &tar[i..i + 512];
+            let mut blank = h.to_vec();
+            blank[148..156].fill(b' ');
+            let sum: u64 = blank.iter().map(|&b| b as u64).sum();
+            assert_eq!(octal(&h[148..156]), sum);
+            assert_eq!(&h[257..265], b"ustar\x0000");
+            let name = String::from_utf8(h[..100].to_vec()).unwrap();
+            let size = octal(&h[124..136]) as usize;
+            entries.push((
+                name.trim_end_matches('\0').to_string(),
+                tar[i + 512..i + 512 + size].to_vec(),
+            ));
+            i += 512 + size.div_ceil(512) * 512;
+        }
+        assert_eq!(tar.len(), i + 1024);
+        entries
+    }
+
+    #[test]
+    fn tar_round_trip() {
+        let gz = GzEncoder::new(Vec::new(), Compression::default());
+        let mut tar = Tar { w: gz, mtime: 0 };
+        let big = vec![b'x'; 1300];
+        tar.add("empty.txt", b"").unwrap();
+        tar.add("detach.txt", b"com.app1\n").unwrap();
+        tar.add("logcat.txt", &big).unwrap();
+        let gz = tar.finish().unwrap().finish().unwrap();
+
+        let mut tar = Vec::new();
+        GzDecoder::new(&gz[..]).read_to_end(&mut tar).unwrap();
+        assert_eq!(tar.len() % 512, 0);
+        let entries = untar(&tar);
+        let names: Vec<&str> = entries.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "zygisk-detach-bugreport/empty.txt",
+                "zygisk-detach-bugreport/detach.txt",
+                "zygisk-detach-bugreport/logcat.txt"
+            ]
+        );
+        assert_eq!(entries[0].1, b"");
+        assert_eq!(entries[1].1, b"com.app1\n");
+        assert_eq!(entries[2].1, big);
+    }
+
+    #[test]
+    fn redacts_apps_but_not_stores() {
+        assert_eq!(redact("com.app1"), redact("com.app1"));
+        assert_ne!(redact("com.app1"), redact("org.xxx2"));
+        assert!(redact("com.app1").starts_with("app-"));
+        assert_eq!(redact("com.android.vending"), "com.android.vending");
+    }
+
+    fn detach_bin() -> Vec<u8> {
+        let mut bin = crate::app_record("com.app1", None).unwrap();
+        bin.extend(crate::app_record("org.xxx2", Some(10)).unwrap());
+        bin.extend(crate::bin_record("com.android.vending", &[crate::TAG_STORE]).unwrap());
+        bin
+    }
+
+    #[test]
+    fn masks_the_detached_names() {
+        let bin = detach_bin();
+        let masked = mask_detach_bin(&bin);
+        assert_eq!(masked.len(), bin.len());
+        let user_record = 1 + bin[0] as usize;
+        let store_record = user_record + 1 + bin[user_record] as usize;
+        // lengths, tags and users are kept, and so are the stores
+        assert_eq!(masked[0], bin[0]);
+        assert_eq!(
+            masked[user_record..user_record + 6],
+            bin[user_record..user_record + 6]
+        );
+        assert_eq!(masked[store_record..], bin[store_record..]);
+        for name in [1..user_record, user_record + 6..store_record] {
+            assert!(masked[name].iter().all(|&b| b == b'*' || b == 0));
+        }
+
+        // everything past a corrupted record
+        let mut corrupted = bin[..user_record].to_vec();
+        corrupted.extend([200, b'o', 0, b'r']);
+        let masked = mask_detach_bin(&corrupted);
+        assert_eq!(masked[user_record..], *b"****");
+    }
+
+    #[test]
+    fn decodes_detach_bin() {
+        let bin = detach_bin();
+        assert_eq!(
+            decode_detach_bin(&bin, false),
+            "com.app1\norg.xxx2 user 10\nstore com.android.vending\n"
+        );
+        assert_eq!(
+            decode_detach_bin(&bin, true),
+            format!(
+                "{}\n{} user 10\nstore com.android.vending\n",
+                redact("com.app1"),
+                redact("org.xxx2")
+            )
+        );
+        assert_eq!(
+            decode_detach_bin(&[200, b'c'], false),
+            "corrupted, see detach.bin.hex\n"
+        );
+    }
+}
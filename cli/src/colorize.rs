@@ -1,84 +1,192 @@
+use std::ffi::OsStr;
 use std::fmt::Display;
+use std::sync::OnceLock;
 use termion::{color, style};
 
+const RESET: &str = "\x1b[0m";
+
 pub struct Colored<D> {
     d: D,
     code: &'static str,
+    reset: &'static str,
 }
 
 impl<D: Display> Display for Colored<D> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         f.write_str(self.code)?;
         self.d.fmt(f)?;
-        f.write_str("\x1b[0m")?;
+        f.write_str(self.reset)?;
         Ok(())
     }
 }
 
-#[allow(dead_code)]
-pub trait ToColored: Display + Sized {
-    fn faint(&self) -> Colored<&Self> {
-        Colored {
-            d: self,
-            code: style::Faint.as_ref(),
+/// Escape sequences of the semantic roles, an empty one leaves the text as is
+pub struct Theme {
+    /// highlighted list row and the status bar
+    selected: String,
+    /// hints and inactive rows
+    faint: String,
+    success: String,
+    danger: String,
+    warning: String,
+    prompt: String,
+}
+
+pub const THEMES: [&str; 3] = ["dark", "light", "high-contrast"];
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            selected: format!("{}{}", color::Fg(color::Black), color::Bg(color::White)),
+            faint: style::Faint.to_string(),
+            success: color::Fg(color::Green).to_string(),
+            danger: color::Fg(color::Red).to_string(),
+            warning: color::Fg(color::Yellow).to_string(),
+            prompt: color::Fg(color::Magenta).to_string(),
         }
     }
 
-    fn red(&self) -> Colored<&Self> {
-        Colored {
-            d: self,
-            code: color::Red.fg_str(),
+    /// Faint black is barely visible on a light background, a dark grey is used instead
+    pub fn light() -> Self {
+        Self {
+            selected: format!("{}{}", color::Fg(color::White), color::Bg(color::Blue)),
+            faint: color::Fg(color::AnsiValue::grayscale(10)).to_string(),
+            success: color::Fg(color::AnsiValue::rgb(0, 2, 0)).to_string(),
+            danger: color::Fg(color::AnsiValue::rgb(3, 0, 0)).to_string(),
+            warning: color::Fg(color::AnsiValue::rgb(3, 1, 0)).to_string(),
+            prompt: color::Fg(color::AnsiValue::rgb(2, 0, 2)).to_string(),
         }
     }
 
-    fn white_bg(&self) -> Colored<&Self> {
-        Colored {
-            d: self,
-            code: color::White.bg_str(),
+    pub fn high_contrast() -> Self {
+        Self {
+            selected: format!("{}{}", style::Bold, style::Invert),
+            // dimmed text is what this theme is for avoiding
+            faint: style::Italic.to_string(),
+            success: format!("{}{}", style::Bold, color::Fg(color::LightGreen)),
+            danger: format!("{}{}", style::Bold, color::Fg(color::LightRed)),
+            warning: format!("{}{}", style::Bold, color::Fg(color::LightYellow)),
+            prompt: format!("{}{}", style::Bold, color::Fg(color::LightMagenta)),
         }
     }
 
-    fn green(&self) -> Colored<&Self> {
-        Colored {
-            d: self,
-            code: color::Green.fg_str(),
+    /// For NO_COLOR, only reverse video so the highlighted row can still be told apart
+    pub fn plain() -> Self {
+        Self {
+            selected: style::Invert.to_string(),
+            faint: String::new(),
+            success: String::new(),
+            danger: String::new(),
+            warning: String::new(),
+            prompt: String::new(),
         }
     }
 
-    fn black(&self) -> Colored<&Self> {
-        Colored {
-            d: self,
-            code: color::Black.fg_str(),
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "high-contrast" => Some(Self::high_contrast()),
+            _ => None,
         }
     }
-    fn yellow(&self) -> Colored<&Self> {
-        Colored {
-            d: self,
-            code: color::Yellow.fg_str(),
-        }
+
+    /// An explicit `--theme` wins over NO_COLOR and TERM=dumb, which win over the config
+    pub fn choose(flag: Option<&str>, config: Option<&str>) -> Result<Self, String> {
+        Self::choose_with(flag, config, no_color())
     }
-    fn blue(&self) -> Colored<&Self> {
-        Colored {
-            d: self,
-            code: color::Black.fg_str(),
+
+    fn choose_with(
+        flag: Option<&str>,
+        config: Option<&str>,
+        no_color: bool,
+    ) -> Result<Self, String> {
+        let by_name = |name: &str| {
+            Self::by_name(name).ok_or_else(|| {
+                format!(
+                    "Unknown theme '{name}', expected one of {}",
+                    THEMES.join(", ")
+                )
+            })
+        };
+        if let Some(name) = flag {
+            return by_name(name);
         }
-    }
-    fn magenta(&self) -> Colored<&Self> {
-        Colored {
-            d: self,
-            code: color::Magenta.fg_str(),
+        if no_color {
+            return Ok(Self::plain());
         }
+        config.map_or(Ok(Self::dark()), by_name)
     }
-    fn cyan(&self) -> Colored<&Self> {
+}
+
+fn no_color() -> bool {
+    is_no_color(
+        std::env::var_os("NO_COLOR").as_deref(),
+        std::env::var_os("TERM").as_deref(),
+    )
+}
+
+/// A set but empty NO_COLOR does not count, as https://no-color.org asks
+fn is_no_color(no_color: Option<&OsStr>, term: Option<&OsStr>) -> bool {
+    no_color.is_some_and(|v| !v.is_empty()) || term.is_some_and(|t| t == "dumb")
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// Has no effect once anything was colored
+pub fn set_theme(theme: Theme) {
+    let _ = THEME.set(theme);
+}
+
+fn theme() -> &'static Theme {
+    THEME.get_or_init(|| {
+        if no_color() {
+            Theme::plain()
+        } else {
+            Theme::dark()
+        }
+    })
+}
+
+pub trait ToColored: Display + Sized {
+    fn role(&self, code: &'static str) -> Colored<&Self> {
         Colored {
             d: self,
-            code: color::Cyan.fg_str(),
+            code,
+            reset: if code.is_empty() { "" } else { RESET },
         }
     }
-    fn white(&self) -> Colored<&Self> {
+
+    fn selected(&self) -> Colored<&Self> {
+        self.role(&theme().selected)
+    }
+
+    fn faint(&self) -> Colored<&Self> {
+        self.role(&theme().faint)
+    }
+
+    fn success(&self) -> Colored<&Self> {
+        self.role(&theme().success)
+    }
+
+    fn danger(&self) -> Colored<&Self> {
+        self.role(&theme().danger)
+    }
+
+    fn warning(&self) -> Colored<&Self> {
+        self.role(&theme().warning)
+    }
+
+    fn prompt(&self) -> Colored<&Self> {
+        self.role(&theme().prompt)
+    }
+
+    /// Only undoes the underline, so it can be nested in other colors
+    fn underline(&self) -> Colored<&Self> {
         Colored {
             d: self,
-            code: color::White.fg_str(),
+            code: style::Underline.as_ref(),
+            reset: style::NoUnderline.as_ref(),
         }
     }
 }
@@ -92,3 +200,59 @@ impl<D> std::ops::Deref for Colored<D> {
         &self.d
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roles(theme: &Theme) -> [&str; 6] {
+        [
+            &theme.selected,
+            &theme.faint,
+            &theme.success,
+            &theme.danger,
+            &theme.warning,
+            &theme.prompt,
+        ]
+    }
+
+    #[test]
+    fn every_theme_covers_every_role() {
+        for name in THEMES {
+            let theme = Theme::by_name(name).unwrap();
+            let roles = roles(&theme);
+            for (i, role) in roles.iter().enumerate() {
+                assert!(!role.is_empty(), "{name}: role {i} is not styled");
+                // told apart from each other
+                assert!(!roles[..i].contains(role), "{name}: role {i} repeats");
+            }
+        }
+        assert!(Theme::by_name("solarized").is_none());
+    }
+
+    #[test]
+    fn no_color_disables_styling() {
+        let os = |s| Some(OsStr::new(s));
+        assert!(is_no_color(os("1"), os("xterm-256color")));
+        assert!(is_no_color(None, os("dumb")));
+        assert!(!is_no_color(os(""), os("xterm")));
+        assert!(!is_no_color(None, None));
+
+        let plain = Theme::choose_with(None, Some("light"), true).unwrap();
+        assert_eq!(roles(&plain)[1..], [""; 5]);
+        let theme = Theme::choose_with(None, Some("light"), false).unwrap();
+        assert_eq!(theme.selected, Theme::light().selected);
+        // an explicit --theme still wins
+        let theme = Theme::choose_with(Some("high-contrast"), None, true).unwrap();
+        assert_eq!(theme.selected, Theme::high_contrast().selected);
+        assert!(Theme::choose_with(None, Some("solarized"), true).is_ok());
+        assert!(Theme::choose_with(None, Some("solarized"), false).is_err());
+
+        // an unstyled role writes no reset either
+        assert_eq!("detach:".role("").to_string(), "detach:");
+        assert_eq!(
+            "detach:".role(style::Invert.as_ref()).to_string(),
+            format!("{}detach:{RESET}", style::Invert)
+        );
+    }
+}
@@ -0,0 +1,41 @@
+use std::fs;
+use std::io;
+
+/// `key = value` settings, one per line, `#` starts a comment
+#[derive(Default)]
+pub struct Config(Vec<(String, String)>);
+
+impl Config {
+    /// An empty config if the file does not exist
+    pub fn load(path: &str) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(txt) => Self::parse(&txt).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn parse(txt: &str) -> Result<Self, String> {
+        let mut entries = Vec::new();
+        for (n, line) in txt.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("line {}: expected 'key = value'", n + 1));
+            };
+            entries.push((key.trim().to_string(), value.trim().to_string()));
+        }
+        Ok(Self(entries))
+    }
+
+    /// The last value set for `key`
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
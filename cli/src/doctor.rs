@@ -0,0 +1,511 @@
+use std::fmt::{self, Display};
+use std::fs;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::colorize::ToColored;
+use crate::parcel::headers_len;
+use crate::stores::Store;
+
+#[cfg(target_os = "android")]
+pub const MODULE_DIR: &str = "/data/adb/modules/zygisk-detach";
+#[cfg(target_os = "android")]
+const MODULES: &str = "/data/adb/modules";
+
+#[cfg(target_os = "linux")]
+pub const MODULE_DIR: &str = "module";
+#[cfg(target_os = "linux")]
+const MODULES: &str = "modules";
+
+/// Zygisk implementations for root managers without a built-in one, by module id
+const ZYGISK_MODULES: [(&str, &str); 2] = [("zygisksu", "Zygisk Next"), ("rezygisk", "ReZygisk")];
+
+/// What the module's companion reads detach.bin from is labeled with under /data/adb
+const SELINUX_CONTEXT: &str = "u:object_r:adb_data_file:s0";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+    /// what to do about a warning or failure
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Warn,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Fail,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+impl Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.status {
+            Status::Pass => write!(f, "{}", "[PASS]".success())?,
+            Status::Warn => write!(f, "{}", "[WARN]".warning())?,
+            Status::Fail => write!(f, "{}", "[FAIL]".danger())?,
+        }
+        write!(f, " {}: {}", self.name, self.detail)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n       {}", hint.faint())?;
+        }
+        Ok(())
+    }
+}
+
+impl Check {
+    /// Without colors, for files
+    pub fn plain(&self) -> String {
+        let status = match self.status {
+            Status::Pass => "PASS",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+        };
+        let mut s = format!("[{status}] {}: {}", self.name, self.detail);
+        if let Some(hint) = &self.hint {
+            s.push_str(&format!("\n       {hint}"));
+        }
+        s
+    }
+}
+
+/// Trimmed stdout of a command that exited successfully
+pub fn output(cmd: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(cmd)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    out.status
+        .success()
+        .then(|| String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+fn succeeds(cmd: &str, args: &[&str]) -> bool {
+    Command::new(cmd)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+enum Root {
+    Magisk,
+    /// KernelSU and APatch, Zygisk comes from a module
+    Other,
+}
+
+fn root_manager(checks: &mut Vec<Check>) -> Option<Root> {
+    const NAME: &str = "Root manager";
+    if let Some(version) = output("magisk", &["-v"]) {
+        let code = output("magisk", &["-V"]).unwrap_or_default();
+        checks.push(Check::pass(NAME, format!("Magisk {version} ({code})")));
+        return Some(Root::Magisk);
+    }
+    for (path, name) in [("/data/adb/ksud", "KernelSU"), ("/data/adb/apd", "APatch")] {
+        if Path::new(path).exists() {
+            let version = output(path, &["-V"]).unwrap_or_default();
+            checks.push(Check::pass(
+                NAME,
+                format!("{name} {version}").trim_end().to_string(),
+            ));
+            return Some(Root::Other);
+        }
+    }
+    checks.push(Check::fail(
+        NAME,
+        "none found",
+        "Install Magisk, KernelSU or APatch and run detach as root",
+    ));
+    None
+}
+
+fn zygisk(root: &Root) -> Check {
+    const NAME: &str = "Zygisk";
+    match root {
+        Root::Magisk => {
+            let value = output(
+                "magisk",
+                &["--sqlite", "SELECT value FROM settings WHERE key='zygisk'"],
+            );
+            match value.as_deref() {
+                Some("value=1") => Check::pass(NAME, "enabled in Magisk"),
+                Some(_) => Check::fail(
+                    NAME,
+                    "disabled in Magisk",
+                    "Enable Zygisk in the Magisk app settings and reboot",
+                ),
+                None => Check::warn(
+                    NAME,
+                    "could not read the Magisk settings",
+                    "Make sure Zygisk is enabled in the Magisk app settings",
+                ),
+            }
+        }
+        Root::Other => {
+            for (id, name) in ZYGISK_MODULES {
+                let dir = Path::new(MODULES).join(id);
+                if !dir.exists() {
+                    continue;
+                }
+                if dir.join("disable").exists() || dir.join("remove").exists() {
+                    return Check::fail(
+                        NAME,
+                        format!("{name} is disabled"),
+                        format!("Enable {name} in the root manager and reboot"),
+                    );
+                }
+                return Check::pass(NAME, format!("provided by {name}"));
+            }
+            Check::fail(
+                NAME,
+                "no Zygisk module installed",
+                "Install Zygisk Next or ReZygisk and reboot",
+            )
+        }
+    }
+}
+
+fn module() -> Check {
+    const NAME: &str = "Module";
+    let dir = Path::new(MODULE_DIR);
+    if !dir.exists() {
+        return Check::fail(
+            NAME,
+            format!("'{MODULE_DIR}' does not exist"),
+            "Install the zygisk-detach module and reboot",
+        );
+    }
+    if dir.join("remove").exists() {
+        return Check::fail(
+            NAME,
+            "marked for removal",
+            "Cancel the removal in the root manager or reinstall the module",
+        );
+    }
+    if dir.join("disable").exists() {
+        return Check::fail(
+            NAME,
+            "disabled",
+            "Enable zygisk-detach in the root manager and reboot",
+        );
+    }
+    if dir.join("update").exists() {
+        return Check::warn(NAME, "updated", "Reboot to finish the update");
+    }
+    Check::pass(NAME, "enabled")
+}
+
+/// Like post-fs-data.sh, the stores only need to be off the denylist while it is enforced
+fn denylist(targets: &[&Store]) -> Check {
+    const NAME: &str = "Denylist";
+    if !succeeds("magisk", &["--denylist", "status"]) {
+        return Check::pass(NAME, "not enforced");
+    }
+    let Some(list) = output("magisk", &["--denylist", "ls"]) else {
+        return Check::warn(
+            NAME,
+            "enforced, could not list it",
+            "Make sure the stores are not on the denylist",
+        );
+    };
+    let denied = denied(targets, &list);
+    if denied.is_empty() {
+        Check::pass(NAME, "enforced, no target store on it")
+    } else {
+        Check::fail(
+            NAME,
+            format!("{} on the denylist", denied.join(", ")),
+            format!("Run 'magisk --denylist rm {}' or reboot", denied[0]),
+        )
+    }
+}
+
+/// Packages of `targets` on the `magisk --denylist ls` output, `package|process` lines
+fn denied<'s>(targets: &[&'s Store], list: &str) -> Vec<&'s str> {
+    targets
+        .iter()
+        .map(|s| s.package)
+        .filter(|pkg| list.lines().any(|l| l.split('|').next() == Some(pkg)))
+        .collect()
+}
+
+const MODE: &str = "detach.bin mode";
+
+fn detach_bin() -> Vec<Check> {
+    const NAME: &str = "detach.bin";
+    let content = match fs::read(crate::MODULE_DETACH) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return vec![Check::warn(
+                NAME,
+                "missing, nothing is detached",
+                "Detach apps with 'detach detach <pkg>' or the menu",
+            )];
+        }
+        Err(e) => {
+            return vec![Check::fail(
+                NAME,
+                format!("could not read '{}': {e}", crate::MODULE_DETACH),
+                "Run detach as root",
+            )];
+        }
+    };
+    let check = records(&content);
+    if check.status == Status::Fail {
+        return vec![check];
+    }
+    let mut checks = vec![check];
+    checks.push(match fs::metadata(crate::MODULE_DETACH) {
+        Ok(meta) => mode(meta.permissions().mode(), meta.uid(), meta.gid()),
+        Err(e) => Check::fail(MODE, e.to_string(), "Run detach as root"),
+    });
+
+    const CONTEXT: &str = "detach.bin SELinux context";
+    match selinux_context(crate::MODULE_DETACH) {
+        Some(ctx) if ctx == SELINUX_CONTEXT => checks.push(Check::pass(CONTEXT, ctx)),
+        Some(ctx) => checks.push(Check::warn(
+            CONTEXT,
+            ctx,
+            format!("Run 'chcon {SELINUX_CONTEXT} {}'", crate::MODULE_DETACH),
+        )),
+        None => checks.push(Check::warn(
+            CONTEXT,
+            "unknown",
+            "SELinux is not available or the label could not be read",
+        )),
+    }
+    checks
+}
+
+/// The records of a readable detach.bin, a failure leaves the other checks meaningless
+fn records(content: &[u8]) -> Check {
+    const NAME: &str = "detach.bin";
+    let records = match crate::parse_bin_records(content) {
+        Ok(records) => records,
+        Err(offset) => {
+            return Check::fail(
+                NAME,
+                format!("record at byte {offset} runs past the end"),
+                "Reset it with 'detach reset' and detach the apps again",
+            );
+        }
+    };
+    // the module stops at the first 0 length, as if the file ended there
+    if let Some((range, _)) = records.iter().find(|(_, r)| r.is_empty()) {
+        return Check::fail(
+            NAME,
+            format!("empty record at byte {}", range.start),
+            "Reset it with 'detach reset' and detach the apps again",
+        );
+    }
+    let apps = crate::get_detached_apps(content);
+    let users = apps.iter().filter(|a| a.user.is_some()).count();
+    let stores = records
+        .iter()
+        .filter(|(_, r)| r.len() % 2 == 0 && r.first() == Some(&crate::TAG_STORE))
+        .count();
+    let detail = format!(
+        "{} detached, {users} of them for a single user, {stores} store records",
+        apps.len()
+    );
+    if apps.is_empty() {
+        Check::warn(NAME, detail, "Nothing is detached")
+    } else {
+        Check::pass(NAME, detail)
+    }
+}
+
+/// Any app can rewrite a world-writable detach.bin, the module needs root to read it
+fn mode(mode: u32, uid: u32, gid: u32) -> Check {
+    let mode = mode & 0o7777;
+    let detail = format!("{mode:04o}, owner {uid}:{gid}");
+    if mode & 0o002 != 0 {
+        Check::warn(
+            MODE,
+            detail,
+            format!(
+                "Any app can change it, run 'chmod 600 {}'",
+                crate::MODULE_DETACH
+            ),
+        )
+    } else if uid != 0 || mode & 0o400 == 0 {
+        Check::warn(
+            MODE,
+            detail,
+            format!(
+                "The module reads it as root, run 'chown 0:0 {0}; chmod 600 {0}'",
+                crate::MODULE_DETACH
+            ),
+        )
+    } else {
+        Check::pass(MODE, detail)
+    }
+}
+
+fn selinux_context(path: &str) -> Option<String> {
+    unsafe extern "C" {
+        fn getxattr(path: *const u8, name: *const u8, value: *mut u8, size: usize) -> isize;
+    }
+    let path = std::ffi::CString::new(path).ok()?;
+    let mut buf = [0u8; 256];
+    let n = unsafe {
+        getxattr(
+            path.as_ptr().cast(),
+            c"security.selinux".as_ptr().cast(),
+            buf.as_mut_ptr(),
+            buf.len(),
+        )
+    };
+    let ctx = buf.get(..usize::try_from(n).ok()?)?;
+    let ctx = ctx.strip_suffix(&[0]).unwrap_or(ctx);
+    Some(String::from_utf8_lossy(ctx).into_owned())
+}
+
+pub fn device_sdk() -> Option<u32> {
+    output("getprop", &["ro.build.version.sdk"])?.parse().ok()
+}
+
+fn sdk() -> Check {
+    const NAME: &str = "SDK";
+    match device_sdk() {
+        Some(sdk) => Check::pass(
+            NAME,
+            format!("{sdk}, parcel headers are {} bytes", headers_len(sdk)),
+        ),
+        None => Check::warn(
+            NAME,
+            format!(
+                "unknown, the module assumes {} bytes of parcel headers",
+                headers_len(u32::MAX)
+            ),
+            "Could not read ro.build.version.sdk",
+        ),
+    }
+}
+
+/// Every check in the order they depend on each other
+pub fn run(targets: &[&Store]) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let root = root_manager(&mut checks);
+    if let Some(root) = &root {
+        checks.push(zygisk(root));
+    }
+    checks.push(module());
+    if let Some(Root::Magisk) = root {
+        checks.push(denylist(targets));
+    }
+    checks.extend(detach_bin());
+    checks.push(sdk());
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::STORES;
+
+    #[test]
+    fn renders_checks() {
+        let check = Check::fail("Module", "disabled", "Enable it");
+        assert_eq!(check.plain(), "[FAIL] Module: disabled\n       Enable it");
+        assert_eq!(Check::pass("SDK", "34").plain(), "[PASS] SDK: 34");
+        assert_eq!(
+            check.to_string(),
+            format!(
+                "{} Module: disabled\n       {}",
+                "[FAIL]".danger(),
+                "Enable it".faint()
+            )
+        );
+    }
+
+    #[test]
+    fn checks_detach_bin_records() {
+        let mut content = crate::app_record("com.app1", None).unwrap();
+        content.extend(crate::app_record("org.xxx2", Some(10)).unwrap());
+        content.extend(crate::bin_record("com.android.vending", &[crate::TAG_STORE]).unwrap());
+        let check = records(&content);
+        assert!(check.status == Status::Pass);
+        assert_eq!(
+            check.detail,
+            "2 detached, 1 of them for a single user, 1 store records"
+        );
+
+        assert!(records(&[]).status == Status::Warn);
+        let len = content.len();
+        // the module stops at a 0 length, the records after it are lost
+        content.push(0);
+        content.extend(crate::app_record("com.tool", None).unwrap());
+        let check = records(&content);
+        assert!(check.status == Status::Fail);
+        assert_eq!(check.detail, format!("empty record at byte {len}"));
+        content.truncate(len);
+        content.extend([3, b'c']);
+        let check = records(&content);
+        assert_eq!(
+            check.detail,
+            format!("record at byte {len} runs past the end")
+        );
+    }
+
+    #[test]
+    fn checks_detach_bin_mode() {
+        let check = mode(0o100600, 0, 0);
+        assert!(check.status == Status::Pass);
+        assert_eq!(check.detail, "0600, owner 0:0");
+        assert!(mode(0o100666, 0, 0).hint.unwrap().contains("chmod 600"));
+        assert!(
+            mode(0o100600, 2000, 2000)
+                .hint
+                .unwrap()
+                .contains("chown 0:0")
+        );
+        assert!(mode(0o100200, 0, 0).status == Status::Warn);
+    }
+
+    #[test]
+    fn finds_stores_on_the_denylist() {
+        let targets: Vec<&Store> = STORES.iter().collect();
+        let list = "\
+com.android.vending|com.android.vending
+com.android.vending|com.android.vending:background
+com.google.android.gms|com.google.android.gms.unstable
+com.huawei.appmarketx|com.huawei.appmarketx";
+        assert_eq!(denied(&targets, list), ["com.android.vending"]);
+        assert!(denied(&targets, "").is_empty());
+    }
+}
@@ -0,0 +1,208 @@
+use std::fmt::Display;
+
+use crate::colorize::ToColored;
+
+const SCORE_MATCH: i32 = 16;
+/// First char of a package name segment, the `y` of `com.google.android.youtube`
+const BONUS_DOT: i32 = 24;
+const BONUS_START: i32 = 20;
+const BONUS_SEPARATOR: i32 = 12;
+const BONUS_CONSECUTIVE: i32 = 12;
+const PENALTY_GAP: i32 = 1;
+
+#[derive(Debug, PartialEq)]
+pub struct Match {
+    pub score: i32,
+    /// char indices of the matched characters in the candidate
+    pub positions: Vec<usize>,
+}
+
+/// Case-insensitive subsequence match of `query` in `candidate`,
+/// scored for the best alignment of the query characters.
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<Match> {
+    let q: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+    let c: Vec<char> = candidate.chars().collect();
+    let (n, m) = (q.len(), c.len());
+    if n == 0 {
+        return Some(Match {
+            score: 0,
+            positions: Vec::new(),
+        });
+    }
+    if n > m {
+        return None;
+    }
+    let eq = |i: usize, j: usize| c[j].to_lowercase().eq(q[i].to_lowercase());
+
+    const NONE: i32 = i32::MIN / 2;
+    // score[i][j]: best score of q[..=i] with q[i] matched at c[j]
+    let mut score = vec![vec![NONE; m]; n];
+    let mut from = vec![vec![usize::MAX; m]; n];
+    for (j, s) in score[0].iter_mut().enumerate() {
+        if eq(0, j) {
+            *s = SCORE_MATCH + bonus(&c, j);
+        }
+    }
+    for i in 1..n {
+        // max of score[i - 1][k] + PENALTY_GAP * k over k < j - 1, the gap is then j - k - 1
+        let mut gap_best = (NONE, usize::MAX);
+        for j in i..m {
+            if j >= 2 {
+                let k = j - 2;
+                if score[i - 1][k] > NONE {
+                    let v = score[i - 1][k] + PENALTY_GAP * k as i32;
+                    if v > gap_best.0 {
+                        gap_best = (v, k);
+                    }
+                }
+            }
+            if !eq(i, j) {
+                continue;
+            }
+            let mut best = (NONE, usize::MAX);
+            if gap_best.1 != usize::MAX {
+                best = (gap_best.0 - PENALTY_GAP * (j as i32 - 1), gap_best.1);
+            }
+            if score[i - 1][j - 1] > NONE && score[i - 1][j - 1] + BONUS_CONSECUTIVE > best.0 {
+                best = (score[i - 1][j - 1] + BONUS_CONSECUTIVE, j - 1);
+            }
+            if best.1 != usize::MAX {
+                score[i][j] = best.0 + SCORE_MATCH + bonus(&c, j);
+                from[i][j] = best.1;
+            }
+        }
+    }
+
+    let (mut j, &best) = score[n - 1]
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| **s > NONE)
+        .max_by_key(|(j, s)| (**s, std::cmp::Reverse(*j)))?;
+    let mut positions = vec![0; n];
+    for i in (0..n).rev() {
+        positions[i] = j;
+        j = from[i][j];
+    }
+    Some(Match {
+        score: best,
+        positions,
+    })
+}
+
+fn bonus(c: &[char], j: usize) -> i32 {
+    match j.checked_sub(1).map(|p| c[p]) {
+        None => BONUS_START,
+        Some('.') => BONUS_DOT,
+        Some('_' | '-' | ' ') => BONUS_SEPARATOR,
+        Some(_) => 0,
+    }
+}
+
+/// A candidate with its matched characters underlined when displayed
+#[derive(Clone)]
+pub struct Highlighted<'a> {
+    pub text: &'a str,
+    pub positions: Vec<usize>,
+}
+
+impl Display for Highlighted<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, ch) in self.text.chars().enumerate() {
+            if self.positions.contains(&i) {
+                write!(f, "{}", ch.underline())?;
+            } else {
+                write!(f, "{ch}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Only the text is compared, the same app matched by different queries is equal
+impl PartialEq for Highlighted<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(q: &str, c: &str) -> i32 {
+        fuzzy_match(q, c).expect("should match").score
+    }
+
+    #[test]
+    fn subsequence() {
+        assert!(fuzzy_match("gms", "com.google.android.gms").is_some());
+        assert!(fuzzy_match("cgag", "com.google.android.gms").is_some());
+        assert!(fuzzy_match("smg", "com.google.android.gms").is_none());
+        assert!(fuzzy_match("toolong", "tool").is_none());
+    }
+
+    #[test]
+    fn case_insensitive() {
+        assert!(fuzzy_match("YT", "com.google.android.youtube").is_some());
+        assert!(fuzzy_match("yt", "com.Google.YouTube").is_some());
+    }
+
+    #[test]
+    fn empty_query() {
+        assert_eq!(
+            fuzzy_match("", "com.app"),
+            Some(Match {
+                score: 0,
+                positions: Vec::new()
+            })
+        );
+    }
+
+    #[test]
+    fn dot_boundary_positions() {
+        let m = fuzzy_match("yt", "com.google.android.youtube").unwrap();
+        assert_eq!(m.positions, vec![19, 22]);
+        // prefers the segment starts over the earlier inner 'a'
+        let m = fuzzy_match("ga", "com.google.android.gms").unwrap();
+        assert_eq!(m.positions, vec![4, 11]);
+    }
+
+    #[test]
+    fn ranks_boundary_hits_higher() {
+        assert!(score("yt", "com.google.android.youtube") > score("yt", "com.mystery"));
+        assert!(
+            score("music", "com.google.android.apps.youtube.music") > score("music", "com.amusicx")
+        );
+    }
+
+    #[test]
+    fn ranks_consecutive_higher() {
+        assert!(score("tube", "com.youtube") > score("tube", "com.txuxbxe"));
+    }
+
+    #[test]
+    fn ranks_shorter_gaps_higher() {
+        assert!(score("ab", "x.axb") > score("ab", "x.axxxxb"));
+    }
+
+    #[test]
+    fn highlighted_compares_text_only() {
+        let a = Highlighted {
+            text: "com.app",
+            positions: vec![0],
+        };
+        let b = Highlighted {
+            text: "com.app",
+            positions: vec![4],
+        };
+        assert!(a == b);
+        assert_eq!(
+            Highlighted {
+                text: "ab",
+                positions: vec![1]
+            }
+            .to_string(),
+            format!("a{}", 'b'.underline())
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use termion::event::Key;
+use unicode_width::UnicodeWidthStr;
+
+/// Input line of the search prompts with readline style editing
+#[derive(Default)]
+pub struct LineEdit {
+    buf: String,
+    /// byte offset, always on a char boundary
+    cursor: usize,
+    /// position in the history while browsing it, the line being typed is kept in `draft`
+    hist_idx: Option<usize>,
+    draft: String,
+}
+
+impl LineEdit {
+    pub fn text(&self) -> &str {
+        &self.buf
+    }
+
+    /// Terminal columns between the start of the line and the cursor
+    pub fn cursor_width(&self) -> usize {
+        self.buf[..self.cursor].width()
+    }
+
+    /// Applies an editing key, `false` if `key` does not edit the line
+    pub fn edit(&mut self, key: Key) -> bool {
+        match key {
+            Key::Char(c) if !c.is_control() => {
+                self.buf.insert(self.cursor, c);
+                self.cursor += c.len_utf8();
+            }
+            Key::Backspace | Key::Ctrl('h') => {
+                if let Some(c) = self.buf[..self.cursor].chars().next_back() {
+                    self.cursor -= c.len_utf8();
+                    self.buf.remove(self.cursor);
+                }
+            }
+            Key::Delete | Key::Ctrl('d') => {
+                if self.cursor < self.buf.len() {
+                    self.buf.remove(self.cursor);
+                }
+            }
+            Key::Left | Key::Ctrl('b') => {
+                if let Some(c) = self.buf[..self.cursor].chars().next_back() {
+                    self.cursor -= c.len_utf8();
+                }
+            }
+            Key::Right | Key::Ctrl('f') => {
+                if let Some(c) = self.buf[self.cursor..].chars().next() {
+                    self.cursor += c.len_utf8();
+                }
+            }
+            Key::Home | Key::Ctrl('a') => self.cursor = 0,
+            Key::End | Key::Ctrl('e') => self.cursor = self.buf.len(),
+            Key::CtrlLeft | Key::AltLeft | Key::Alt('b') => self.cursor = self.word_start(),
+            Key::CtrlRight | Key::AltRight | Key::Alt('f') => self.cursor = self.word_end(),
+            Key::Ctrl('u') => {
+                self.buf.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::Ctrl('k') => self.buf.truncate(self.cursor),
+            Key::Ctrl('w') => {
+                let start = self.word_start();
+                self.buf.drain(start..self.cursor);
+                self.cursor = start;
+            }
+            Key::Alt('d') => {
+                let end = self.word_end();
+                self.buf.drain(self.cursor..end);
+            }
+            _ => return false,
+        }
+        self.hist_idx = None;
+        true
+    }
+
+    /// Words are runs of alphanumerics, `com.google.android` has three
+    fn word_start(&self) -> usize {
+        let before = &self.buf[..self.cursor];
+        let end = before
+            .trim_end_matches(|c: char| !c.is_alphanumeric())
+            .len();
+        before[..end]
+            .rfind(|c: char| !c.is_alphanumeric())
+            .map_or(0, |i| {
+                i + before[i..].chars().next().map_or(1, char::len_utf8)
+            })
+    }
+
+    fn word_end(&self) -> usize {
+        let after = &self.buf[self.cursor..];
+        let skipped = after.len()
+            - after
+                .trim_start_matches(|c: char| !c.is_alphanumeric())
+                .len();
+        self.cursor
+            + after[skipped..]
+                .find(|c: char| !c.is_alphanumeric())
+                .map_or(after.len(), |i| skipped + i)
+    }
+
+    fn set(&mut self, line: &str) {
+        self.buf = line.to_string();
+        self.cursor = self.buf.len();
+    }
+
+    /// Replaces the line with the previous entry of `history`
+    pub fn history_prev(&mut self, history: &[String]) {
+        let idx = match self.hist_idx {
+            None if history.is_empty() => return,
+            None => {
+                self.draft = self.buf.clone();
+                history.len() - 1
+            }
+            Some(i) => i.saturating_sub(1),
+        };
+        self.set(&history[idx]);
+        self.hist_idx = Some(idx);
+    }
+
+    /// Goes forward in `history`, back to the typed line after the newest entry
+    pub fn history_next(&mut self, history: &[String]) {
+        let Some(idx) = self.hist_idx else {
+            return;
+        };
+        if idx + 1 < history.len() {
+            self.set(&history[idx + 1]);
+            self.hist_idx = Some(idx + 1);
+        } else {
+            let draft = std::mem::take(&mut self.draft);
+            self.set(&draft);
+            self.hist_idx = None;
+        }
+    }
+
+    pub fn is_browsing_history(&self) -> bool {
+        self.hist_idx.is_some()
+    }
+}
+
+/// Appends `line` to the session history unless it is blank or repeats the last entry
+pub fn remember(history: &mut Vec<String>, line: &str) {
+    let line = line.trim();
+    if !line.is_empty() && history.last().is_none_or(|l| l != line) {
+        history.push(line.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(s: &str) -> LineEdit {
+        let mut line = LineEdit::default();
+        for c in s.chars() {
+            assert!(line.edit(Key::Char(c)));
+        }
+        line
+    }
+
+    fn keys(line: &mut LineEdit, keys: &[Key]) {
+        for &key in keys {
+            assert!(line.edit(key));
+        }
+    }
+
+    #[test]
+    fn cursor_columns_of_wide_and_combining_chars() {
+        let mut line = typed("a카톡b");
+        assert_eq!(line.cursor_width(), 6);
+        keys(&mut line, &[Key::Left, Key::Left]);
+        assert_eq!(line.cursor_width(), 3);
+        keys(&mut line, &[Key::Backspace]);
+        assert_eq!((line.text(), line.cursor_width()), ("a톡b", 1));
+
+        // the accent takes no column of its own
+        let mut line = typed("cafe\u{301}");
+        assert_eq!(line.cursor_width(), 4);
+        keys(&mut line, &[Key::Left]);
+        assert_eq!(line.cursor_width(), 4);
+        keys(&mut line, &[Key::Left]);
+        assert_eq!(line.cursor_width(), 3);
+        keys(&mut line, &[Key::Delete]);
+        assert_eq!(line.text(), "caf\u{301}");
+    }
+
+    #[test]
+    fn word_jumps() {
+        let mut line = typed("com.google.android");
+        keys(&mut line, &[Key::CtrlLeft]);
+        assert_eq!(line.cursor_width(), 11);
+        keys(&mut line, &[Key::Alt('b'), Key::AltLeft]);
+        assert_eq!(line.cursor_width(), 0);
+        keys(&mut line, &[Key::CtrlRight]);
+        assert_eq!(line.cursor_width(), 3);
+        keys(&mut line, &[Key::Alt('f')]);
+        assert_eq!(line.cursor_width(), 10);
+        keys(&mut line, &[Key::Alt('d')]);
+        assert_eq!(line.text(), "com.google");
+        keys(&mut line, &[Key::Ctrl('w')]);
+        assert_eq!((line.text(), line.cursor_width()), ("com.", 4));
+
+        let mut line = typed("앱.카톡");
+        keys(&mut line, &[Key::AltLeft]);
+        assert_eq!(line.cursor_width(), 3);
+    }
+
+    #[test]
+    fn kills_around_the_cursor() {
+        let mut line = typed("org.xxx2");
+        keys(&mut line, &[Key::Home, Key::Right, Key::Right, Key::Right]);
+        keys(&mut line, &[Key::Ctrl('k')]);
+        assert_eq!((line.text(), line.cursor_width()), ("org", 3));
+
+        let mut line = typed("org.xxx2");
+        keys(&mut line, &[Key::Ctrl('a'), Key::Ctrl('f'), Key::Ctrl('f')]);
+        keys(&mut line, &[Key::Ctrl('f'), Key::Ctrl('u')]);
+        assert_eq!((line.text(), line.cursor_width()), (".xxx2", 0));
+        keys(&mut line, &[Key::Ctrl('e'), Key::Ctrl('u')]);
+        assert_eq!(line.text(), "");
+        assert!(!line.edit(Key::Char('\n')));
+        assert!(!line.edit(Key::Up));
+    }
+
+    #[test]
+    fn history_navigation() {
+        let mut history = Vec::new();
+        for entry in ["com.app1", " ", "org.xxx2", "org.xxx2 "] {
+            remember(&mut history, entry);
+        }
+        assert_eq!(history, ["com.app1", "org.xxx2"]);
+
+        let mut line = typed("dra");
+        line.history_next(&history);
+        assert_eq!(line.text(), "dra");
+        line.history_prev(&history);
+        assert_eq!(line.text(), "org.xxx2");
+        assert!(line.is_browsing_history());
+        line.history_prev(&history);
+        line.history_prev(&history);
+        assert_eq!((line.text(), line.cursor_width()), ("com.app1", 8));
+        line.history_next(&history);
+        assert_eq!(line.text(), "org.xxx2");
+        line.history_next(&history);
+        assert_eq!(line.text(), "dra");
+        assert!(!line.is_browsing_history());
+
+        // editing a recalled entry leaves the history
+        line.history_prev(&history);
+        keys(&mut line, &[Key::Char('3')]);
+        assert!(!line.is_browsing_history());
+        assert_eq!(line.text(), "org.xxx23");
+
+        let mut line = typed("x");
+        line.history_prev(&[]);
+        assert_eq!(line.text(), "x");
+    }
+}
@@ -0,0 +1,262 @@
+use std::fmt::{self, Display};
+
+pub const TAG: &str = "zygisk-detach";
+
+/// A message of the module, see the `LOGD`s in zygisk/jni/module.cpp
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    /// the transact hook is in place
+    Loaded,
+    NotTarget(String),
+    /// `ro.build.version.sdk` could not be read, the headers length of SDK 30+ is used
+    SdkFallback,
+    HookCommitFailed,
+    LibbinderNotFound,
+    /// nothing is detached
+    EmptyDetachBin,
+    /// the store process could not get detach.bin from the companion
+    ReadFailed(String),
+    /// the companion, running as root, could not send detach.bin
+    CompanionFailed(String),
+    Unknown(String),
+}
+
+impl Event {
+    pub fn parse(msg: &str) -> Self {
+        if let Some(store) = msg.strip_suffix(" is not a target store") {
+            return Self::NotTarget(store.to_string());
+        }
+        match msg {
+            "Loaded!" => Self::Loaded,
+            "WARN: could not get sdk version (fallback=3)" => Self::SdkFallback,
+            "ERROR: pltHookCommit" => Self::HookCommitFailed,
+            "ERROR: Module not found!" => Self::LibbinderNotFound,
+            "ERROR: detach.bin <= 0" => Self::EmptyDetachBin,
+            "ERROR: read companion size" => Self::ReadFailed("size".to_string()),
+            _ => {
+                if let Some(read) = msg.strip_prefix("ERROR: read companion, ") {
+                    Self::ReadFailed(read.to_string())
+                } else if let Some(err) = msg.strip_prefix("ERROR: ").filter(|err| {
+                    ["companion open", "write remote_fd", "fstat", "sendfile"]
+                        .iter()
+                        .any(|c| err.starts_with(c))
+                }) {
+                    Self::CompanionFailed(err.to_string())
+                } else {
+                    Self::Unknown(msg.to_string())
+                }
+            }
+        }
+    }
+
+    /// Whether the process logging it ends up without the hook
+    fn is_failure(&self) -> bool {
+        !matches!(self, Self::Loaded | Self::SdkFallback | Self::Unknown(_))
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Loaded => write!(f, "hook loaded"),
+            Self::NotTarget(store) => write!(f, "{store} is not a target store"),
+            Self::SdkFallback => write!(f, "SDK unknown, assuming 30+"),
+            Self::HookCommitFailed => write!(f, "could not hook libbinder"),
+            Self::LibbinderNotFound => write!(f, "libbinder is not loaded"),
+            Self::EmptyDetachBin => write!(f, "detach.bin is empty or missing"),
+            Self::ReadFailed(what) => {
+                write!(f, "could not read detach.bin from the companion: {what}")
+            }
+            Self::CompanionFailed(what) => write!(f, "companion error: {what}"),
+            Self::Unknown(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    /// `MM-DD hh:mm:ss.mmm`, `None` for the brief format
+    pub time: Option<String>,
+    pub pid: u32,
+    pub event: Event,
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(time) = &self.time {
+            write!(f, "{time} ")?;
+        }
+        write!(f, "[{}] {}", self.pid, self.event)
+    }
+}
+
+impl Entry {
+    /// A line of `logcat -v threadtime` or `-v brief` output, `None` for other tags and the
+    /// `--------- beginning of` separators
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        // D/zygisk-detach( 1234): Loaded!
+        if let Some(rest) = line.get(2..).filter(|_| line.get(1..2) == Some("/")) {
+            let rest = rest.strip_prefix(TAG)?.trim_start().strip_prefix('(')?;
+            let (pid, msg) = rest.split_once("):")?;
+            return Some(Self {
+                time: None,
+                pid: pid.trim().parse().ok()?,
+                event: Event::parse(msg.trim_start()),
+            });
+        }
+        // 10-19 12:34:56.789  1234  1250 D zygisk-detach: Loaded!
+        let mut rest = line;
+        let date = field(&mut rest)?;
+        let time = field(&mut rest)?;
+        let pid = field(&mut rest)?.parse().ok()?;
+        let _tid = field(&mut rest)?;
+        let _level = field(&mut rest)?;
+        let msg = rest
+            .trim_start()
+            .strip_prefix(TAG)?
+            .trim_start()
+            .strip_prefix(':')?;
+        Some(Self {
+            time: Some(format!("{date} {time}")),
+            pid,
+            event: Event::parse(msg.trim_start()),
+        })
+    }
+}
+
+/// Splits off the next space separated field of `s`
+fn field<'a>(s: &mut &'a str) -> Option<&'a str> {
+    let trimmed = s.trim_start();
+    let end = trimmed.find(' ').unwrap_or(trimmed.len());
+    let (field, rest) = trimmed.split_at(end);
+    *s = rest;
+    (!field.is_empty()).then_some(field)
+}
+
+pub fn parse(logcat: &str) -> Vec<Entry> {
+    logcat.lines().filter_map(Entry::parse).collect()
+}
+
+/// Whether the hook loaded in the store process `pid`, `None` if the store is not running
+pub fn summary(entries: &[Entry], label: &str, pid: Option<u32>) -> String {
+    let Some(pid) = pid else {
+        return format!("{label}: not running");
+    };
+    let events: Vec<&Event> = entries
+        .iter()
+        .filter(|e| e.pid == pid)
+        .map(|e| &e.event)
+        .collect();
+    if let Some(failure) = events.iter().rev().find(|e| e.is_failure()) {
+        format!("{label} ({pid}): not hooked, {failure}")
+    } else if events.contains(&&Event::Loaded) {
+        format!("{label} ({pid}): hooked")
+    } else {
+        format!("{label} ({pid}): no log, the module did not run in it or the log was rotated")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THREADTIME: &str = "\
+--------- beginning of main
+10-19 09:12:01.204  2211  2211 D zygisk-detach: Loaded!
+10-19 09:12:03.318  2650  2650 D zygisk-detach: com.sec.android.app.samsungapps is not a target store
+10-19 09:14:44.002   812   812 D zygisk-detach: ERROR: companion open
+10-19 09:14:44.010  3120  3120 D zygisk-detach: ERROR: read companion size
+10-19 09:15:10.551  3301  3301 D zygisk-detach: WARN: could not get sdk version (fallback=3)
+10-19 09:15:10.552  3301  3301 D zygisk-detach: ERROR: pltHookCommit
+";
+
+    #[test]
+    fn parses_threadtime() {
+        let entries = parse(THREADTIME);
+        assert_eq!(entries.len(), 6);
+        assert_eq!(
+            entries[0],
+            Entry {
+                time: Some("10-19 09:12:01.204".to_string()),
+                pid: 2211,
+                event: Event::Loaded
+            }
+        );
+        assert_eq!(
+            entries[1].event,
+            Event::NotTarget("com.sec.android.app.samsungapps".to_string())
+        );
+        assert_eq!(
+            entries[2].event,
+            Event::CompanionFailed("companion open".to_string())
+        );
+        assert_eq!(entries[3].event, Event::ReadFailed("size".to_string()));
+        assert_eq!(entries[4].event, Event::SdkFallback);
+        assert_eq!(entries[5].event, Event::HookCommitFailed);
+    }
+
+    #[test]
+    fn parses_brief() {
+        let entries = parse(
+            "\
+D/zygisk-detach( 4242): ERROR: Module not found!
+D/zygisk-detach( 4243): ERROR: read companion, 12 bytes
+D/zygisk-detach(  990): ERROR: detach.bin <= 0
+I/ActivityManager( 1000): Start proc 4242:com.android.vending/u0a150
+",
+        );
+        assert_eq!(
+            entries,
+            [
+                Entry {
+                    time: None,
+                    pid: 4242,
+                    event: Event::LibbinderNotFound
+                },
+                Entry {
+                    time: None,
+                    pid: 4243,
+                    event: Event::ReadFailed("12 bytes".to_string())
+                },
+                Entry {
+                    time: None,
+                    pid: 990,
+                    event: Event::EmptyDetachBin
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_other_tags_and_garbage() {
+        let entries = parse(
+            "\
+10-19 09:12:01.204  1000  1000 I ActivityManager: zygisk-detach: Loaded!
+not a log line
+
+10-19 09:12:01.204  2211  2211 D zygisk-detach: something new
+",
+        );
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            entries[0].event,
+            Event::Unknown("something new".to_string())
+        );
+    }
+
+    #[test]
+    fn summarizes_store_process() {
+        let entries = parse(THREADTIME);
+        assert_eq!(summary(&entries, "Play", Some(2211)), "Play (2211): hooked");
+        assert_eq!(
+            summary(&entries, "Play", Some(3301)),
+            "Play (3301): not hooked, could not hook libbinder"
+        );
+        assert_eq!(
+            summary(&entries, "Play", Some(1)),
+            "Play (1): no log, the module did not run in it or the log was rotated"
+        );
+        assert_eq!(summary(&entries, "Play", None), "Play: not running");
+    }
+}
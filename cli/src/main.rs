@@ -1,31 +1,101 @@
 use std::error::Error;
 use std::fmt::{Debug, Display};
 use std::fs::{self, File, OpenOptions};
-use std::io::{self, Seek};
+use std::io::{self, IsTerminal, Seek};
 use std::io::{BufWriter, Read, Write};
 use std::mem::size_of;
 use std::ops::Range;
 use std::panic::Location;
-use std::process::{Command, ExitCode};
+#[cfg(target_os = "android")]
+use std::process::Command;
+use std::process::ExitCode;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use termion::event::Key;
-use termion::{clear, cursor, terminal_size};
+use termion::{clear, cursor};
+
+mod aidl;
+
+mod apk;
+
+mod bugreport;
 
 mod colorize;
-use colorize::ToColored;
+use colorize::{Theme, ToColored};
+
+mod config;
+use config::Config;
+
+mod doctor;
+
+mod fuzzy;
+
+mod line_edit;
+
+mod logs;
 
 mod menus;
-use menus::Menus;
+use menus::{KeySource, Menus};
+
+mod mounts;
+
+mod parcel;
+
+mod pkg_cache;
+
+mod pm_codes;
+
+mod process;
+use process::Stop;
+
+mod signature;
+use signature::AllowList;
+
+mod stores;
+use stores::Store;
+
+mod term;
+
+mod tui;
+
+#[cfg(test)]
+mod vt;
 
 #[cfg(target_os = "android")]
 const MODULE_DETACH: &str = "/data/adb/zygisk-detach/detach.bin";
 #[cfg(target_os = "android")]
 const DETACH_TXT: &str = "/data/adb/modules/zygisk-detach/detach.txt";
+#[cfg(target_os = "android")]
+const DETACH_DATES: &str = "/data/adb/zygisk-detach/detach_dates.txt";
+
+#[cfg(target_os = "android")]
+const CONFIG: &str = "/data/adb/zygisk-detach/config";
+#[cfg(target_os = "android")]
+const MOUNTINFO: &str = "/proc/self/mountinfo";
+#[cfg(target_os = "android")]
+const SIGNATURES: &str = "/data/adb/zygisk-detach/signatures.txt";
+#[cfg(target_os = "android")]
+const PKG_CACHE: &str = "/data/adb/zygisk-detach/packages.cache";
+#[cfg(target_os = "android")]
+const PKG_SOURCES: [&str; 2] = ["/data/system/packages.list", "/data/system/packages.xml"];
 
 #[cfg(target_os = "linux")]
 const MODULE_DETACH: &str = "detach.bin";
 #[cfg(target_os = "linux")]
 const DETACH_TXT: &str = "detach.txt";
+#[cfg(target_os = "linux")]
+const DETACH_DATES: &str = "detach_dates.txt";
+#[cfg(target_os = "linux")]
+const CONFIG: &str = "config";
+#[cfg(target_os = "linux")]
+const MOUNTINFO: &str = "mountinfo";
+#[cfg(target_os = "linux")]
+const SIGNATURES: &str = "signatures.txt";
+#[cfg(target_os = "linux")]
+const PKG_CACHE: &str = "packages.cache";
+#[cfg(target_os = "linux")]
+const PKG_SOURCES: [&str; 2] = ["packages.list", "packages.xml"];
 
 struct LocErr<E: Error> {
     source: E,
@@ -53,8 +123,47 @@ impl From<io::Error> for LocErr<io::Error> {
 }
 type IOResult<T> = Result<T, LocErr<io::Error>>;
 
+const USAGE: &str = "\
+Usage: detach [--refresh] [--no-kill] [--theme dark|light|high-contrast] [command]
+Without a command the interactive menu is shown.
+With --no-kill the changes are saved but the store is not restarted until 'detach apply'.
+With --refresh the installed packages are listed again instead of read from the cache.
+The theme can also be set with 'theme = <name>' in the config file,
+the targeted stores with 'stores = play, galaxy'.
+
+Commands:
+  detach [--user N] <pkg>...   detach packages
+  reattach [--user N] <pkg>    re-attach a package
+  list [--user N | --all]      list the detached packages,
+                               --all adds the ones of single users, marked with theirs
+  detachall <pkg>...           replace the detached packages
+  reset                        re-attach everything
+  apk <path>...                detach the packages of apk files
+  scan-mounts [--detach]       find bind-mounted apps
+  candidates [--all]           find re-signed apps via signatures.txt,
+                               --all lists every installed or mounted app
+  cache clear                  drop the installed package cache
+  stores [--packages]          list the stores, targets marked with *
+  stores sync                  write the target stores to detach.bin after a config change
+  apply [--user N]             restart the target stores so they reload detach.bin
+  restart [--user N]           stop the target stores, listing their processes
+  batch                        run commands from stdin, one per line, then 'apply'
+  doctor                       check the install and explain what is wrong
+  logs [--follow | <file>]     summarize the module's logcat, or a saved one
+  parcel decode [--sdk N] [FILE]
+                               pretty-print a hex or binary parcel dump, stdin by default
+  simulate [--sdk N] [--code N] [--user N] <pkg>
+                               run a package through the module's matcher
+  bugreport [--redact]         bundle diagnostics into a tar.gz for an issue,
+                               --redact replaces the package names
+  serialize <txt> <bin>        convert a detach.txt to detach.bin
+  aidl-gen <dir> <rs> <hpp>    generate the IPackageManager transaction code tables
+                               from the IPackageManager-<sdk>.aidl files of dir";
+
 fn main() -> ExitCode {
     std::panic::set_hook(Box::new(|panic| {
+        // with panic = "abort" no destructor gets to do it
+        term::restore();
         let mut stderr = io::stderr();
         let _ = writeln!(stderr, "\r\n{panic}\r\n");
         let _ = writeln!(stderr, "This should not have happened.");
@@ -68,143 +177,853 @@ fn main() -> ExitCode {
         std::fs::create_dir("/data/adb/zygisk-detach/").expect("zygisk-detach path");
     }
 
-    let mut args = std::env::args().skip(1);
-    if let Some(cmd) = args.next().as_deref() {
-        match cmd {
-            "serialize" => {
-                let Some(dtxt) = args.next() else {
-                    eprintln!("ERROR: detach.txt path not supplied.");
+    let mut args = std::env::args().skip(1).peekable();
+    let mut refresh = false;
+    let mut theme = None;
+    loop {
+        if args.next_if(|arg| arg == "--refresh").is_some() {
+            refresh = true;
+        } else if args.next_if(|arg| arg == "--no-kill").is_some() {
+            NO_KILL.store(true, Ordering::Relaxed);
+        } else if args.next_if(|arg| arg == "--theme").is_some() {
+            let Some(name) = args.next() else {
+                eprintln!(
+                    "ERROR: --theme needs one of {}",
+                    colorize::THEMES.join(", ")
+                );
+                return ExitCode::FAILURE;
+            };
+            theme = Some(name);
+        } else {
+            break;
+        }
+    }
+    let cmd = args.next();
+    // only the menu and doctor are colored, the other commands do not read the config for it
+    let config = match (&theme, cmd.as_deref()) {
+        (None, None | Some("doctor" | "batch")) => load_config(),
+        _ => Config::default(),
+    };
+    match Theme::choose(theme.as_deref(), config.get("theme")) {
+        Ok(theme) => colorize::set_theme(theme),
+        Err(e) if theme.is_some() => {
+            eprintln!("ERROR: {e}");
+            return ExitCode::FAILURE;
+        }
+        Err(e) => eprintln!("WARNING: '{CONFIG}': {e}"),
+    }
+
+    if let Some(cmd) = cmd {
+        let ret = run(&cmd, args.collect::<Vec<_>>().into_iter(), refresh);
+        if PENDING.load(Ordering::Relaxed) {
+            println!("Changes are saved. Run 'detach apply' to apply them.");
+        }
+        return ret;
+    }
+    if NO_KILL.load(Ordering::Relaxed) {
+        eprintln!("ERROR: --no-kill needs a command\n\n{USAGE}");
+        return ExitCode::FAILURE;
+    }
+
+    #[cfg(target_os = "android")]
+    for store in target_stores() {
+        let _ = Command::new("magisk")
+            .args(["--denylist", "rm", store.package])
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .and_then(|mut p| p.wait());
+    }
+
+    // adb shell without -t, scripts and pipes
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        eprintln!(
+            "ERROR: The interactive menu needs a terminal, pass a command instead.\n\n{USAGE}"
+        );
+        return ExitCode::FAILURE;
+    }
+    let mut menus = match Menus::new() {
+        Ok(menus) => menus,
+        Err(err) => {
+            eprintln!("ERROR: Could not set up the terminal: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let ret = match interactive(&mut menus, refresh) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("\rERROR: {err}");
+            ExitCode::FAILURE
+        }
+    };
+    let _ = menus.cursor_show();
+    ret
+}
+
+/// Takes `--user <id>` out of `args`
+fn take_user_arg(args: &mut Vec<String>) -> Result<Option<u32>, String> {
+    take_num_arg(args, "--user", "Android user id")
+}
+
+/// Removes `<flag> <n>` from `args`
+fn take_num_arg(args: &mut Vec<String>, flag: &str, what: &str) -> Result<Option<u32>, String> {
+    let Some(i) = args.iter().position(|a| a == flag) else {
+        return Ok(None);
+    };
+    args.remove(i);
+    if i >= args.len() {
+        return Err(format!("{flag} needs an {what}"));
+    }
+    let n = args.remove(i);
+    n.parse()
+        .map(Some)
+        .map_err(|_| format!("invalid {what}: '{n}'"))
+}
+
+fn run(cmd: &str, mut args: std::vec::IntoIter<String>, refresh: bool) -> ExitCode {
+    match cmd {
+        "serialize" => {
+            let Some(dtxt) = args.next() else {
+                eprintln!("ERROR: detach.txt path not supplied.");
+                return ExitCode::FAILURE;
+            };
+            let Some(dbin) = args.next() else {
+                eprintln!("ERROR: detach.bin path not supplied.");
+                return ExitCode::FAILURE;
+            };
+            if let Err(err) = serialize_txt(&dtxt, &dbin) {
+                eprintln!("ERROR: {err}");
+                return ExitCode::FAILURE;
+            }
+            println!("Serialized detach.txt");
+            ExitCode::SUCCESS
+        }
+        "aidl-gen" => {
+            let (Some(dir), Some(rs), Some(hpp)) = (args.next(), args.next(), args.next()) else {
+                eprintln!("ERROR: Expected the AIDL directory and the .rs and .hpp paths");
+                return ExitCode::FAILURE;
+            };
+            match aidl::generate(&dir, &rs, &hpp) {
+                Ok(n) => {
+                    println!("Generated the transaction codes of {n} SDKs");
+                    ExitCode::SUCCESS
+                }
+                Err(err) => {
+                    eprintln!("ERROR: {err}");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        "detachall" => {
+            if args.len() == 0 {
+                eprintln!("ERROR: No Package name(s) was supplied.");
+                return ExitCode::FAILURE;
+            }
+            let args: Vec<String> = args.collect();
+            if let Err(e) = replace_detached(&args) {
+                eprintln!("ERROR: Could not write detach.bin: {e}");
+                return ExitCode::FAILURE;
+            }
+            apply_report(None).iter().for_each(|l| println!("{l}"));
+            ExitCode::SUCCESS
+        }
+        "detach" => {
+            let mut args: Vec<String> = args.collect();
+            let user = match take_user_arg(&mut args) {
+                Ok(user) => user,
+                Err(e) => {
+                    eprintln!("ERROR: {e}");
                     return ExitCode::FAILURE;
+                }
+            };
+            if args.is_empty() {
+                eprintln!("ERROR: No Package name(s) was supplied.");
+                return ExitCode::FAILURE;
+            }
+            if let Some(user) = user {
+                let installed = match get_installed_apps_cached(Some(user), refresh) {
+                    Ok(installed) => installed,
+                    Err(e) => {
+                        eprintln!("ERROR: Could not list the packages of user {user}: {e}");
+                        return ExitCode::FAILURE;
+                    }
                 };
-                let Some(dbin) = args.next() else {
-                    eprintln!("ERROR: detach.bin path not supplied.");
+                let installed = parse_installed_apps(&installed);
+                for pkg_name in &args {
+                    if !installed.iter().any(|app| app.name == pkg_name) {
+                        println!("not installed for user {user}: {}", pkg_name);
+                    }
+                }
+            }
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            let detached = match write_detached(&args, user) {
+                Ok(detached) => detached,
+                Err(e) => {
+                    eprintln!("ERROR: Could not write detach.bin: {e}");
                     return ExitCode::FAILURE;
+                }
+            };
+            for pkg_name in args.iter().filter(|app| !detached.contains(app)) {
+                println!("already detached: {}", pkg_name);
+            }
+            if !detached.is_empty() {
+                apply_report(user).iter().for_each(|l| println!("{l}"));
+            }
+            ExitCode::SUCCESS
+        }
+        "apk" => {
+            if args.len() == 0 {
+                eprintln!("ERROR: No APK path was supplied.");
+                return ExitCode::FAILURE;
+            }
+            let mut n = 0;
+            let mut status = ExitCode::SUCCESS;
+            for path in args {
+                // on errors the stores still get the packages of the previous paths
+                let pkgs = match apk::package_names(std::path::Path::new(&path)) {
+                    Ok(pkgs) if pkgs.is_empty() => {
+                        eprintln!("ERROR: No APKs found in '{path}'");
+                        status = ExitCode::FAILURE;
+                        break;
+                    }
+                    Ok(pkgs) => pkgs,
+                    Err(e) => {
+                        eprintln!("ERROR: Could not read '{path}': {e}");
+                        status = ExitCode::FAILURE;
+                        break;
+                    }
                 };
-                if let Err(err) = serialize_txt(&dtxt, &dbin) {
-                    eprintln!("ERROR: {err}");
+                let pkgs: Vec<&str> = pkgs.iter().map(String::as_str).collect();
+                let detached = match write_detached(&pkgs, None) {
+                    Ok(detached) => detached,
+                    Err(e) => {
+                        eprintln!("ERROR: Could not write detach.bin: {e}");
+                        status = ExitCode::FAILURE;
+                        break;
+                    }
+                };
+                for pkg_name in pkgs {
+                    if detached.contains(&pkg_name) {
+                        println!("detach: {}", pkg_name);
+                    } else {
+                        println!("already detached: {}", pkg_name);
+                    }
+                }
+                n += detached.len();
+            }
+            if n > 0 {
+                apply_report(None).iter().for_each(|l| println!("{l}"));
+            }
+            status
+        }
+        "scan-mounts" => {
+            let detach = match args.next().as_deref() {
+                None => false,
+                Some("--detach") => true,
+                Some(arg) => {
+                    eprintln!("ERROR: Unexpected argument: {arg}");
                     return ExitCode::FAILURE;
                 }
-                println!("Serialized detach.txt");
-                return ExitCode::SUCCESS;
+            };
+            let (mounted, detached) = match get_mounted_apps(refresh) {
+                Ok(v) => v,
+                Err(e) => {
+                    eprintln!("ERROR: Could not scan mounts: {e}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            let mut to_detach = Vec::new();
+            for pkg_name in &mounted {
+                if detached.contains(pkg_name) {
+                    println!("detached: {}", pkg_name);
+                } else if detach {
+                    to_detach.push(pkg_name.as_str());
+                    println!("detach: {}", pkg_name);
+                } else {
+                    println!("not detached: {}", pkg_name);
+                }
             }
-            "detachall" => {
-                if args.len() == 0 {
-                    eprintln!("ERROR: No Package name(s) was supplied.");
+            if !to_detach.is_empty() {
+                if let Err(e) = write_detached(&to_detach, None) {
+                    eprintln!("ERROR: Could not write detach.bin: {e}");
                     return ExitCode::FAILURE;
                 }
-                let mut f = fs::OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .truncate(true)
-                    .open(MODULE_DETACH)
-                    .expect("open detach.bin");
-                for pkg_name in args {
-                    bin_serialize(&pkg_name, &mut f).expect("write to detach.bin");
-                }
-                detach_bin_changed();
-                println!("Changes are applied. No need for a reboot!");
-                return ExitCode::SUCCESS;
-            }
-            "detach" => {
-                if args.len() == 0 {
-                    eprintln!("ERROR: No Package name(s) was supplied.");
+                apply_report(None).iter().for_each(|l| println!("{l}"));
+            }
+            ExitCode::SUCCESS
+        }
+        "candidates" => {
+            let all = match args.next().as_deref() {
+                None => false,
+                Some("--all") => true,
+                Some(arg) => {
+                    eprintln!("ERROR: Unexpected argument: {arg}");
                     return ExitCode::FAILURE;
                 }
-                for pkg_name in args {
-                    if !detach_by_name(&pkg_name).expect("detach.txt") {
-                        println!("already detached: {}", pkg_name);
+            };
+            let allow = match fs::read_to_string(SIGNATURES) {
+                Ok(txt) => match AllowList::parse(&txt) {
+                    Ok(allow) => allow,
+                    Err(e) => {
+                        eprintln!("ERROR: '{SIGNATURES}': {e}");
+                        return ExitCode::FAILURE;
                     }
+                },
+                Err(e) => {
+                    eprintln!("ERROR: Could not read the allow-list '{SIGNATURES}': {e}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            let (checks, detached) = match check_signatures(&allow, refresh) {
+                Ok(v) => v,
+                Err(e) => {
+                    eprintln!("ERROR: {e}");
+                    return ExitCode::FAILURE;
                 }
-                println!("Changes are applied. No need for a reboot!");
-                return ExitCode::SUCCESS;
-            }
-            "reset" => {
-                match fs::OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .truncate(true)
-                    .open(MODULE_DETACH)
-                {
-                    Ok(_) => detach_bin_changed(),
-                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            };
+            let mut unlisted = 0;
+            for check in checks {
+                if check.is_candidate() && !detached.contains(&check.pkg) {
+                    println!("candidate: {check}");
+                } else if all {
+                    println!("{check}");
+                } else if matches!(check.status, signature::SigStatus::Unlisted) {
+                    unlisted += 1;
+                }
+            }
+            if unlisted > 0 {
+                println!(
+                    "{unlisted} apps have no known upstream signature in '{SIGNATURES}', \
+                     'candidates --all' lists them"
+                );
+            }
+            ExitCode::SUCCESS
+        }
+        "cache" => {
+            match args.next().as_deref() {
+                Some("clear") => match pkg_cache::clear(PKG_CACHE) {
+                    Ok(0) => println!("Package cache is already empty"),
+                    Ok(_) => println!("Cleared the package cache"),
                     Err(e) => {
-                        eprintln!("ERROR: Could not delete '{MODULE_DETACH}': {e}");
+                        eprintln!("ERROR: Could not clear the package cache: {e}");
                         return ExitCode::FAILURE;
                     }
+                },
+                _ => {
+                    eprintln!("ERROR: Expected 'cache clear'");
+                    return ExitCode::FAILURE;
                 }
-                return ExitCode::SUCCESS;
             }
-            "reattach" => {
-                let Some(pkg_name) = args.next() else {
-                    eprintln!("ERROR: package name not supplied.");
+            ExitCode::SUCCESS
+        }
+        "reset" => {
+            match fs::OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(MODULE_DETACH)
+            {
+                Ok(_) => apply_report(None).iter().for_each(|l| println!("{l}")),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    eprintln!("ERROR: Could not delete '{MODULE_DETACH}': {e}");
                     return ExitCode::FAILURE;
-                };
-                if reattach_by_name(&pkg_name).expect("detach.txt") {
-                    println!("re-attached: {}", pkg_name);
-                }
-                return ExitCode::SUCCESS;
-            }
-            "list" => {
-                let mut detach_txt = match fs::OpenOptions::new()
-                    .write(true)
-                    .read(true)
-                    .open(MODULE_DETACH)
-                {
-                    Ok(f) => f,
-                    Err(e) if e.kind() == io::ErrorKind::NotFound => return ExitCode::SUCCESS,
-                    Err(e) => {
-                        eprintln!("ERROR: Could not list detached pkgs: {e}");
+                }
+            }
+            ExitCode::SUCCESS
+        }
+        "reattach" => {
+            let mut args: Vec<String> = args.collect();
+            let user = match take_user_arg(&mut args) {
+                Ok(user) => user,
+                Err(e) => {
+                    eprintln!("ERROR: {e}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            let Some(pkg_name) = args.first() else {
+                eprintln!("ERROR: package name not supplied.");
+                return ExitCode::FAILURE;
+            };
+            let removed = match remove_detached(&[pkg_name], user) {
+                Ok(removed) => removed,
+                Err(e) => {
+                    eprintln!("ERROR: Could not write detach.bin: {e}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            if !removed.is_empty() {
+                println!("re-attached: {}", pkg_name);
+                apply_report(user).iter().for_each(|l| println!("{l}"));
+            }
+            ExitCode::SUCCESS
+        }
+        "list" => {
+            let mut args: Vec<String> = args.collect();
+            let user = match take_user_arg(&mut args) {
+                Ok(user) => user,
+                Err(e) => {
+                    eprintln!("ERROR: {e}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            let all = match args.as_slice() {
+                [] => false,
+                [flag] if flag == "--all" && user.is_none() => true,
+                [arg, ..] => {
+                    eprintln!("ERROR: Unexpected argument: {arg}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            let mut detach_txt = match fs::OpenOptions::new()
+                .write(true)
+                .read(true)
+                .open(MODULE_DETACH)
+            {
+                Ok(f) => f,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("ERROR: Could not list detached pkgs: {e}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            let mut content = Vec::new();
+            match detach_txt.read_to_end(&mut content) {
+                Ok(0) => return ExitCode::SUCCESS,
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("ERROR: {e}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            // bare names, the WebUI hands them back to detachall
+            for app in get_detached_apps(&content) {
+                if all {
+                    println!("{app}");
+                } else if app.applies_to(user) {
+                    println!("{}", app.name);
+                }
+            }
+            ExitCode::SUCCESS
+        }
+        "stores" => {
+            let packages = match args.next().as_deref() {
+                None => false,
+                Some("--packages") => true,
+                Some("sync") => {
+                    if let Err(e) = write_store_records(&target_stores()) {
+                        eprintln!("ERROR: Could not update '{MODULE_DETACH}': {e}");
                         return ExitCode::FAILURE;
                     }
-                };
-                let mut content = Vec::new();
-                match detach_txt.read_to_end(&mut content) {
-                    Ok(0) => return ExitCode::SUCCESS,
-                    Ok(_) => {}
+                    return ExitCode::SUCCESS;
+                }
+                Some(arg) => {
+                    eprintln!("ERROR: Unexpected argument: {arg}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            let targets = target_stores();
+            if packages {
+                for store in &targets {
+                    println!("{}", store.package);
+                }
+            } else {
+                for store in &stores::STORES {
+                    let mark = if targets.iter().any(|t| t.name == store.name) {
+                        '*'
+                    } else {
+                        ' '
+                    };
+                    println!(
+                        "{mark} {:<12}{} ({})",
+                        store.name, store.label, store.package
+                    );
+                }
+            }
+            ExitCode::SUCCESS
+        }
+        "apply" => {
+            let mut args: Vec<String> = args.collect();
+            let user = match take_user_arg(&mut args) {
+                Ok(user) => user,
+                Err(e) => {
+                    eprintln!("ERROR: {e}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            if !args.is_empty() {
+                eprintln!("ERROR: Unexpected argument: {}", args.join(" "));
+                return ExitCode::FAILURE;
+            }
+            let (lines, applied) = apply(user);
+            lines.iter().for_each(|l| println!("{l}"));
+            if applied {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        "batch" => {
+            if args.len() != 0 {
+                eprintln!("ERROR: batch reads the commands from stdin");
+                return ExitCode::FAILURE;
+            }
+            // one restart at the end instead of one per command
+            NO_KILL.store(true, Ordering::Relaxed);
+            IN_BATCH.store(true, Ordering::Relaxed);
+            let mut ret = ExitCode::SUCCESS;
+            for (n, line) in io::stdin().lines().enumerate() {
+                let line = match line {
+                    Ok(line) => line,
                     Err(e) => {
-                        eprintln!("ERROR: {e}");
-                        return ExitCode::FAILURE;
+                        eprintln!("ERROR: Could not read stdin: {e}");
+                        ret = ExitCode::FAILURE;
+                        break;
                     }
                 };
-                for (app, _) in get_detached_apps(&content) {
-                    println!("{app}");
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let mut words = line.split_whitespace().map(String::from);
+                let cmd = words.next().unwrap_or_default();
+                if cmd == "batch" {
+                    eprintln!("ERROR: line {}: batch cannot be nested", n + 1);
+                    ret = ExitCode::FAILURE;
+                    continue;
+                }
+                if run(&cmd, words.collect::<Vec<_>>().into_iter(), refresh) != ExitCode::SUCCESS {
+                    eprintln!("ERROR: line {}: '{line}' failed", n + 1);
+                    ret = ExitCode::FAILURE;
                 }
-                return ExitCode::SUCCESS;
             }
-            s => {
-                eprintln!(
-                    "\
-Unexpected command: {s}
-Accepted commands are: 'detach', 'detachall', 'reset', 'list', 'serialize'"
-                );
+            NO_KILL.store(false, Ordering::Relaxed);
+            IN_BATCH.store(false, Ordering::Relaxed);
+            if PENDING.load(Ordering::Relaxed) {
+                let (lines, applied) = apply(None);
+                lines.iter().for_each(|l| println!("{l}"));
+                if !applied {
+                    ret = ExitCode::FAILURE;
+                }
+            }
+            ret
+        }
+        "doctor" => {
+            let checks = doctor::run(&target_stores());
+            for check in &checks {
+                println!("{check}");
+            }
+            if checks.iter().any(|c| c.status == doctor::Status::Fail) {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            }
+        }
+        "logs" => {
+            let (follow, file) = match args.next() {
+                None => (false, None),
+                Some(arg) if arg == "--follow" => (true, None),
+                Some(file) => (false, Some(file)),
+            };
+            if let Some(arg) = args.next() {
+                eprintln!("ERROR: Unexpected argument: {arg}");
                 return ExitCode::FAILURE;
             }
+            match show_logs(follow, file.as_deref()) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("ERROR: {e}");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        "bugreport" => {
+            #[cfg(target_os = "android")]
+            const DIR: &str = "/sdcard";
+            #[cfg(target_os = "linux")]
+            const DIR: &str = ".";
+            let redact = match args.next().as_deref() {
+                None => false,
+                Some("--redact") => true,
+                Some(arg) => {
+                    eprintln!("ERROR: Unexpected argument: {arg}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            match bugreport::write(DIR, redact) {
+                Ok(path) => {
+                    println!("Wrote {path}, attach it to the issue");
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("ERROR: Could not write the bug report: {e}");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        "parcel" => {
+            let mut args: Vec<String> = args.collect();
+            if args.first().map(String::as_str) != Some("decode") {
+                eprintln!("ERROR: Expected 'parcel decode'");
+                return ExitCode::FAILURE;
+            }
+            args.remove(0);
+            let sdk = match take_num_arg(&mut args, "--sdk", "SDK level") {
+                Ok(sdk) => sdk,
+                Err(e) => {
+                    eprintln!("ERROR: {e}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            let dump = match args.as_slice() {
+                [] if IN_BATCH.load(Ordering::Relaxed) => {
+                    eprintln!("ERROR: Expected a file, stdin holds the batch");
+                    return ExitCode::FAILURE;
+                }
+                [] => {
+                    let mut dump = Vec::new();
+                    io::stdin().read_to_end(&mut dump).map(|_| dump)
+                }
+                [file] => fs::read(file),
+                _ => {
+                    eprintln!("ERROR: Expected at most one file");
+                    return ExitCode::FAILURE;
+                }
+            };
+            match dump
+                .map_err(|e| e.to_string())
+                .and_then(|d| decode_parcel(&d, sdk))
+            {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("ERROR: {e}");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        "simulate" => {
+            let mut args: Vec<String> = args.collect();
+            let mut opt = |flag, what| take_num_arg(&mut args, flag, what);
+            let (sdk, code, user) = match (
+                opt("--sdk", "SDK level"),
+                opt("--code", "transaction code"),
+                opt("--user", "Android user id"),
+            ) {
+                (Ok(sdk), Ok(code), Ok(user)) => (sdk, code, user),
+                (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+                    eprintln!("ERROR: {e}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            let [pkg] = args.as_slice() else {
+                eprintln!("ERROR: Expected one package name");
+                return ExitCode::FAILURE;
+            };
+            if let Err(e) = simulate(pkg, sdk, code, user.unwrap_or(0)) {
+                eprintln!("ERROR: {e}");
+                return ExitCode::FAILURE;
+            }
+            ExitCode::SUCCESS
+        }
+        "restart" => {
+            let mut args: Vec<String> = args.collect();
+            let user = match take_user_arg(&mut args) {
+                Ok(user) => user,
+                Err(e) => {
+                    eprintln!("ERROR: {e}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            if !args.is_empty() {
+                eprintln!("ERROR: Unexpected argument: {}", args.join(" "));
+                return ExitCode::FAILURE;
+            }
+            let stops = match kill_store(user) {
+                Ok(stops) => stops,
+                Err(e) => {
+                    eprintln!("ERROR: Could not stop the store: {e}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            for stop in &stops {
+                for p in &stop.found {
+                    let state = if stop.remaining.iter().any(|r| r.pid == p.pid) {
+                        "still running"
+                    } else {
+                        "stopped"
+                    };
+                    println!("{state}: {p}");
+                }
+                println!("{stop}");
+            }
+            if !stops.iter().all(Stop::applied) {
+                return ExitCode::FAILURE;
+            }
+            ExitCode::SUCCESS
+        }
+        "help" | "-h" | "--help" => {
+            println!("{USAGE}");
+            ExitCode::SUCCESS
+        }
+        s => {
+            eprintln!("Unexpected command: {s}\n\n{USAGE}");
+            ExitCode::FAILURE
         }
     }
+}
 
-    #[cfg(target_os = "android")]
-    let _ = Command::new("magisk")
-        .args(["--denylist", "rm", "com.android.vending"])
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::null())
-        .spawn()
-        .and_then(|mut p| p.wait());
-
-    let mut menus = Menus::new();
-    let ret = match interactive(&mut menus) {
-        Ok(()) => ExitCode::SUCCESS,
-        Err(err) => {
-            eprintln!("\rERROR: {err}");
-            ExitCode::FAILURE
+/// Prints the module's log messages, followed by whether the stores are hooked unless `follow`
+fn show_logs(follow: bool, file: Option<&str>) -> IOResult<()> {
+    let mut logcat = std::process::Command::new("logcat");
+    logcat.args(["-b", "main", "-v", "threadtime", "-s", logs::TAG]);
+    if follow {
+        let mut child = logcat.stdout(std::process::Stdio::piped()).spawn()?;
+        let stdout = child.stdout.take().expect("piped stdout");
+        for line in io::BufRead::lines(io::BufReader::new(stdout)) {
+            if let Some(entry) = logs::Entry::parse(&line?) {
+                println!("{entry}");
+            }
+        }
+        child.wait()?;
+        return Ok(());
+    }
+    let text = match file {
+        Some(file) => fs::read_to_string(file)?,
+        None => {
+            let out = logcat.arg("-d").output()?;
+            String::from_utf8_lossy(&out.stdout).into_owned()
+        }
+    };
+    let entries = logs::parse(&text);
+    for entry in &entries {
+        println!("{entry}");
+    }
+    if entries.is_empty() {
+        println!("No messages from the module in the log");
+    }
+    for store in target_stores() {
+        // pid of the main process, the module only runs in it
+        let pid = process::find(&[store.package], None)?
+            .into_iter()
+            .find(|p| p.cmdline.split(' ').next() == Some(store.package))
+            .map(|p| p.pid as u32);
+        println!("{}", logs::summary(&entries, store.label, pid));
+    }
+    Ok(())
+}
+
+/// Prints the fields of a parcel dump and whether the module's `enforceInterface` takes it,
+/// for `sdk` or for each headers layout
+fn decode_parcel(dump: &[u8], sdk: Option<u32>) -> Result<(), String> {
+    // one SDK of each headers layout
+    const LAYOUTS: [(u32, &str); 3] = [(30, "SDK 30+"), (29, "SDK 29"), (28, "SDK 28-")];
+    let parcel = parcel::parse_dump(dump)?;
+    let headers_len = match sdk {
+        Some(sdk) => {
+            println!("{} bytes, headers of SDK {sdk}", parcel.len());
+            parcel::headers_len(sdk)
+        }
+        None => match parcel::guess_headers_len(&parcel) {
+            Some(h) => {
+                let (_, layout) = LAYOUTS
+                    .iter()
+                    .find(|(s, _)| parcel::headers_len(*s) == h)
+                    .unwrap();
+                println!(
+                    "{} bytes, headers of {layout} going by the interface token",
+                    parcel.len()
+                );
+                h
+            }
+            None => {
+                println!(
+                    "{} bytes, no interface token found, reading the headers of SDK 30+",
+                    parcel.len()
+                );
+                parcel::headers_len(30)
+            }
+        },
+    };
+    for field in parcel::decode(&parcel, headers_len) {
+        println!("{field}");
+    }
+    let layouts = match sdk {
+        Some(sdk) => vec![(sdk, format!("SDK {sdk}"))],
+        None => LAYOUTS.iter().map(|(s, l)| (*s, l.to_string())).collect(),
+    };
+    for (sdk, layout) in layouts {
+        let verdict = parcel::enforce_interface(&parcel, sdk)
+            .err()
+            .unwrap_or_else(|| "accepted".to_string());
+        println!("enforceInterface of {layout}: {verdict}");
+    }
+    Ok(())
+}
+
+/// Runs `pkg` through the module's matcher as a store of `user` would send it
+fn simulate(pkg: &str, sdk: Option<u32>, code: Option<u32>, user: u32) -> IOResult<()> {
+    let sdk = match sdk.or_else(doctor::device_sdk) {
+        Some(sdk) => sdk,
+        None => {
+            println!("Could not read the SDK level, assuming 30+ like the module");
+            30
         }
     };
-    menus.cursor_show().unwrap();
-    ret
+    // every code but getPackageInfo's is matched
+    let code = code.unwrap_or_else(|| parcel::pm_code(sdk, "isPackageAvailable"));
+    let detach_bin = match fs::read(MODULE_DETACH) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            println!("{MODULE_DETACH} does not exist, nothing is detached");
+            Vec::new()
+        }
+        Err(e) => return Err(e.into()),
+    };
+    // an app uid of `user`, only the user id matters
+    let uid = user * 100000 + 10000;
+    let module = parcel::Module::new(&detach_bin, sdk, uid);
+    let loaded: Vec<&str> = stores::STORES
+        .iter()
+        .filter(|s| module.is_target(s.package))
+        .map(|s| s.label)
+        .collect();
+    println!("The module stays loaded in: {}", loaded.join(", "));
+
+    let parcel = parcel::synthesize(sdk, parcel::PM_DESCRIPTOR, pkg, user);
+    println!(
+        "SDK {sdk}: {} bytes of headers, a {} byte parcel, transaction code {code}{}, user {user}",
+        parcel::headers_len(sdk),
+        parcel.len(),
+        parcel::pm_method(sdk, code).map_or(String::new(), |m| format!(" ({m})"))
+    );
+    println!("{pkg}: {}", module.check(&parcel, code));
+    Ok(())
 }
 
-fn detach_bin_changed() {
+/// Set by `--no-kill` and `batch`, the store is then only restarted by `apply`
+static NO_KILL: AtomicBool = AtomicBool::new(false);
+/// detach.bin changed while `NO_KILL` was set
+static PENDING: AtomicBool = AtomicBool::new(false);
+/// Set while `batch` runs its lines, stdin is not the commands' own then
+static IN_BATCH: AtomicBool = AtomicBool::new(false);
+
+fn detach_bin_changed(user: Option<u32>) -> IOResult<Vec<Stop>> {
+    let _ = fs::remove_file(DETACH_TXT);
+    let _ = write_store_records(&target_stores());
+    if NO_KILL.load(Ordering::Relaxed) {
+        PENDING.store(true, Ordering::Relaxed);
+        return Ok(Vec::new());
+    }
+    kill_store(user)
+}
+
+/// Restarts the target stores for the changes saved with `NO_KILL`, one line per store
+/// and whether all of them reload detach.bin
+fn apply(user: Option<u32>) -> (Vec<String>, bool) {
     let _ = fs::remove_file(DETACH_TXT);
-    let _ = kill_store();
+    let _ = write_store_records(&target_stores());
+    PENDING.store(false, Ordering::Relaxed);
+    stop_report(kill_store(user))
 }
 
 fn serialize_txt(txt: &str, bin: &str) -> IOResult<()> {
@@ -219,22 +1038,43 @@ fn serialize_txt(txt: &str, bin: &str) -> IOResult<()> {
         .filter(|l| !l.is_empty() && !l.starts_with('#'))
     {
         println!("  '{}'", app);
-        bin_serialize(app, &mut detach_bin)?;
+        bin_serialize(app, None, &mut detach_bin)?;
+    }
+    for store in target_stores() {
+        detach_bin.write_all(&bin_record(store.package, &[TAG_STORE])?)?;
     }
     Ok(())
 }
 
-fn interactive(menus: &mut Menus) -> IOResult<()> {
+fn interactive(menus: &mut Menus, refresh: bool) -> IOResult<()> {
+    // loaded once per session
+    let mut installed_apps = None;
     menus.cursor_hide()?;
     print!("zygisk-detach cli by github.com/j-hc\r\n\n");
     loop {
         match main_menu(menus)? {
-            Op::DetachSelect => detach_menu(menus)?,
+            Op::DetachSelect => {
+                let installed_apps = match &installed_apps {
+                    Some(apps) => apps,
+                    None => installed_apps.insert(get_installed_apps_cached(None, refresh)?),
+                };
+                detach_menu(menus, installed_apps)?
+            }
             Op::ReattachSelect => reattach_menu(menus)?,
+            Op::AppManager => {
+                let installed_apps = match &installed_apps {
+                    Some(apps) => apps,
+                    None => installed_apps.insert(get_installed_apps_cached(None, refresh)?),
+                };
+                tui::app_manager(menus, installed_apps)?
+            }
+            Op::ScanMounts => scan_mounts_menu(menus, refresh)?,
             Op::Reset => {
                 if fs::remove_file(MODULE_DETACH).is_ok() {
-                    let _ = kill_store();
-                    text!(menus, "Reset");
+                    textln!(menus, "Reset");
+                    for line in apply_report(None) {
+                        textln!(menus, "{line}");
+                    }
                 } else {
                     text!(menus, "Already empty");
                 }
@@ -258,7 +1098,7 @@ fn interactive(menus: &mut Menus) -> IOResult<()> {
     }
 }
 
-fn reattach_menu(menus: &mut Menus) -> IOResult<()> {
+fn reattach_menu<W: Write, K: KeySource>(menus: &mut Menus<W, K>) -> IOResult<()> {
     let mut detach_txt = match fs::OpenOptions::new()
         .write(true)
         .read(true)
@@ -280,26 +1120,39 @@ fn reattach_menu(menus: &mut Menus) -> IOResult<()> {
         text!(menus, "detach.bin is empty");
         return Ok(());
     }
-    let list = detached_apps.iter().map(|e| e.0.as_str());
-    let Some(i) = menus.select_menu(
-        list,
-        "Select the app to re-attach ('q' to leave):",
-        "✖".red(),
+    let Some(selected) = menus.checklist(
+        &detached_apps,
+        "Select the apps to re-attach ('q' to leave):",
+        false,
         Some(Key::Char('q')),
     )?
     else {
         return Ok(());
     };
 
-    textln!(menus, "{}: {}", "re-attach".red(), detached_apps[i].0);
-    content.drain(detached_apps[i].1.clone());
+    // drain from the back so the earlier ranges stay valid
+    for &i in selected.iter().rev() {
+        textln!(menus, "{}: {}", "re-attach".danger(), detached_apps[i]);
+        content.drain(detached_apps[i].range.clone());
+    }
     detach_txt.set_len(0)?;
     detach_txt.write_all(&content)?;
-    detach_bin_changed();
+    for &i in &selected {
+        forget_detach_dates(&[&detached_apps[i].name], detached_apps[i].user)?;
+    }
+    let mut user = detached_apps[selected[0]].user;
+    if selected.iter().any(|&i| detached_apps[i].user != user) {
+        user = None;
+    }
+    for line in apply_report(user) {
+        textln!(menus, "{line}");
+    }
     Ok(())
 }
 
-fn reattach_by_name(pkg_name: &str) -> IOResult<bool> {
+/// Removes the entries of `apps` detached for exactly `user` without notifying the store,
+/// returns the removed ones
+fn remove_detached<'a>(apps: &[&'a str], user: Option<u32>) -> IOResult<Vec<&'a str>> {
     let mut detach_txt = fs::OpenOptions::new()
         .write(true)
         .read(true)
@@ -307,64 +1160,300 @@ fn reattach_by_name(pkg_name: &str) -> IOResult<bool> {
     let mut content = Vec::new();
     detach_txt.read_to_end(&mut content)?;
     detach_txt.seek(io::SeekFrom::Start(0))?;
-    let detached_apps = get_detached_apps(&content);
-    let Some(reattach_app) = detached_apps.iter().find(|(name, _)| name == pkg_name) else {
-        return Ok(false);
-    };
-    content.drain(reattach_app.1.clone());
-    detach_txt.set_len(0)?;
-    detach_txt.write_all(&content)?;
-    detach_bin_changed();
-    Ok(true)
+    let mut removed = Vec::new();
+    // drain from the back so the earlier ranges stay valid
+    for app in get_detached_apps(&content).iter().rev() {
+        if app.user == user
+            && let Some(&name) = apps.iter().find(|&&name| name == app.name)
+        {
+            content.drain(app.range.clone());
+            removed.push(name);
+        }
+    }
+    if !removed.is_empty() {
+        detach_txt.set_len(0)?;
+        detach_txt.write_all(&content)?;
+        forget_detach_dates(&removed, user)?;
+    }
+    Ok(removed)
+}
+
+/// detach.bin is a list of `[len: u8][record]`.
+/// An odd `len` is an app detached for every user, its name encoded by `bin_serialize`.
+/// An even `len` is a tagged record, `[tag: u8][payload]`, which older modules skip.
+const TAG_USER_APP: u8 = 1;
+/// `[TAG_STORE][encoded package]`, a store the module hooks. Only the Play Store if there are none.
+const TAG_STORE: u8 = 2;
+
+struct DetachedApp {
+    name: String,
+    /// Android user the app is detached for, every user if `None`
+    user: Option<u32>,
+    range: Range<usize>,
+}
+
+impl DetachedApp {
+    fn applies_to(&self, user: Option<u32>) -> bool {
+        self.user.is_none() || self.user == user
+    }
+}
+
+impl Display for DetachedApp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.user {
+            Some(user) => write!(f, "{} (user {user})", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// A record of detach.bin and its range, length byte included
+type BinRecord<'a> = (Range<usize>, &'a [u8]);
+
+/// Every record of detach.bin
+fn bin_records(detach_txt: &[u8]) -> Vec<BinRecord<'_>> {
+    parse_bin_records(detach_txt)
+        .unwrap_or_else(|_| term::fatal("Corrupted detach.bin. Reset and try again."))
 }
 
-fn get_detached_apps(detach_txt: &[u8]) -> Vec<(String, Range<usize>)> {
+/// `bin_records`, or the offset of the record that runs past the end
+fn parse_bin_records(detach_txt: &[u8]) -> Result<Vec<BinRecord<'_>>, usize> {
     let mut i = 0;
-    let mut detached = Vec::new();
+    let mut records = Vec::new();
     while i < detach_txt.len() {
         let len: u8 = detach_txt[i];
         const SZ_LEN: usize = size_of::<u8>();
         i += SZ_LEN;
-        let Some(encoded_name) = &detach_txt.get(i..i + len as usize) else {
-            eprintln!("Corrupted detach.bin. Reset and try again.");
-            std::process::exit(1);
+        let Some(record) = detach_txt.get(i..i + len as usize) else {
+            return Err(i - SZ_LEN);
         };
-        let name = String::from_utf8(encoded_name.iter().step_by(2).cloned().collect()).unwrap();
-        detached.push((name, i - SZ_LEN..i + len as usize));
+        records.push((i - SZ_LEN..i + len as usize, record));
         i += len as usize;
     }
+    Ok(records)
+}
+
+fn get_detached_apps(detach_txt: &[u8]) -> Vec<DetachedApp> {
+    let mut detached = Vec::new();
+    for (range, record) in bin_records(detach_txt) {
+        let (encoded_name, user) = if record.len() % 2 == 1 {
+            (record, None)
+        } else {
+            const SZ_USER: usize = size_of::<u32>();
+            match record.split_first() {
+                Some((&TAG_USER_APP, rest)) if rest.len() > SZ_USER => {
+                    let (user, name) = rest.split_at(SZ_USER);
+                    (name, Some(u32::from_le_bytes(user.try_into().unwrap())))
+                }
+                _ => continue,
+            }
+        };
+        let name = String::from_utf8(encoded_name.iter().step_by(2).cloned().collect()).unwrap();
+        detached.push(DetachedApp { name, user, range });
+    }
     detached
 }
 
+/// Replaces the TAG_STORE records of detach.bin with the configured target stores
+fn write_store_records(targets: &[&Store]) -> IOResult<()> {
+    let mut content = match fs::read(MODULE_DETACH) {
+        Ok(content) => content,
+        // nothing detached, nothing for the module to do
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    let stale: Vec<Range<usize>> = bin_records(&content)
+        .into_iter()
+        .filter(|(_, r)| r.len() % 2 == 0 && r.first() == Some(&TAG_STORE))
+        .map(|(range, _)| range)
+        .collect();
+    let mut records = Vec::new();
+    for store in targets {
+        records.extend(bin_record(store.package, &[TAG_STORE])?);
+    }
+    let current: Vec<u8> = stale
+        .iter()
+        .flat_map(|r| content[r.clone()].to_vec())
+        .collect();
+    if current == records {
+        return Ok(());
+    }
+    for range in stale.into_iter().rev() {
+        content.drain(range);
+    }
+    content.extend(records);
+    fs::write(MODULE_DETACH, content)?;
+    Ok(())
+}
+
+/// `get_installed_apps` through the package cache, which is only rescanned
+/// after a package change or when `refresh` is set
+fn get_installed_apps_cached(user: Option<u32>, refresh: bool) -> IOResult<Vec<u8>> {
+    // without a key the cache could go stale unnoticed
+    let Some(key) = pkg_cache::key(&PKG_SOURCES) else {
+        return get_installed_apps(user);
+    };
+    let path = pkg_cache::path(PKG_CACHE, user);
+    if !refresh && let Some(apps) = pkg_cache::load(&path, &key) {
+        return Ok(apps);
+    }
+    let apps = get_installed_apps(user)?;
+    let _ = pkg_cache::store(&path, &key, &apps);
+    Ok(apps)
+}
+
 #[cfg(target_os = "linux")]
-fn get_installed_apps() -> IOResult<Vec<u8>> {
-    Ok("package:com.app1\npackage:org.xxx2\ncom.apppppppp.tooolonnggggtooolonnggggtooolonnggggtooolonngggg".as_bytes().to_vec())
+fn get_installed_apps(_user: Option<u32>) -> IOResult<Vec<u8>> {
+    Ok("\
+package:/data/app/~~aaa==/com.app1-bbb==/base.apk=com.app1
+package:/data/app/org.xxx2-1/base.apk=org.xxx2
+package:/system/app/Tool/Tool.apk=com.apppppppp.tooolonnggggtooolonnggggtooolonnggggtooolonngggg
+"
+    .as_bytes()
+    .to_vec())
 }
 
+/// `pm list packages -f`
 #[cfg(target_os = "android")]
-fn get_installed_apps() -> IOResult<Vec<u8>> {
-    let op = Command::new("pm")
-        .args(["list", "packages"])
+fn get_installed_apps(user: Option<u32>) -> IOResult<Vec<u8>> {
+    let mut pm = Command::new("pm");
+    pm.args(["list", "packages", "-f"]);
+    if let Some(user) = user {
+        pm.args(["--user", &user.to_string()]);
+    }
+    let op = pm
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
         .output()?;
     if !op.status.success() {
-        panic!("pm: '{}'", String::from_utf8_lossy(&op.stderr));
+        let stderr = String::from_utf8_lossy(&op.stderr);
+        return Err(io::Error::other(format!("pm: '{}'", stderr.trim())).into());
     }
     Ok(op.stdout)
 }
 
-#[derive(Clone, Copy)]
+struct InstalledApp<'a> {
+    name: &'a str,
+    apk_path: &'a str,
+}
+
+impl InstalledApp<'_> {
+    /// installed to or updated in /data, not only a system image app
+    fn is_user_installed(&self) -> bool {
+        self.apk_path.starts_with("/data/")
+    }
+}
+
+fn parse_installed_apps(pm_out: &[u8]) -> Vec<InstalledApp<'_>> {
+    std::str::from_utf8(pm_out)
+        .expect("non utf-8 package names?")
+        .lines()
+        .map(|l| {
+            let (apk_path, name) = l
+                .strip_prefix("package:")
+                .and_then(|l| l.rsplit_once('='))
+                .expect("unexpected output from pm");
+            InstalledApp { name, apk_path }
+        })
+        .collect()
+}
+
+fn get_detached_names() -> IOResult<Vec<String>> {
+    match fs::read(MODULE_DETACH) {
+        Ok(content) => Ok(get_detached_apps(&content)
+            .into_iter()
+            .map(|app| app.name)
+            .collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Apps with a bind mount over their installed APK and the currently detached apps
+fn get_mounted_apps(refresh: bool) -> IOResult<(Vec<String>, Vec<String>)> {
+    let mountinfo = fs::read_to_string(MOUNTINFO)?;
+    let apk_paths = get_installed_apps_cached(None, refresh)?;
+    let mounted = mounts::mounted_apps(&mountinfo, &String::from_utf8_lossy(&apk_paths));
+    Ok((mounted, get_detached_names()?))
+}
+
+/// Signature checks of the apps installed to /data, bind-mounted over a system app or in
+/// the allow-list, and the currently detached apps
+fn check_signatures(
+    allow: &AllowList,
+    refresh: bool,
+) -> IOResult<(Vec<signature::SigCheck>, Vec<String>)> {
+    let apk_paths = get_installed_apps_cached(None, refresh)?;
+    let mounted = mounts::mounted_apps(
+        &fs::read_to_string(MOUNTINFO)?,
+        &String::from_utf8_lossy(&apk_paths),
+    );
+    let checks = parse_installed_apps(&apk_paths)
+        .iter()
+        .filter(|app| {
+            app.is_user_installed()
+                || mounted.iter().any(|m| m == app.name)
+                || allow.packages().any(|p| p == app.name)
+        })
+        .map(|app| signature::inspect(app.name, std::path::Path::new(app.apk_path), allow))
+        .collect();
+    Ok((checks, get_detached_names()?))
+}
+
+fn scan_mounts_menu<W: Write, K: KeySource>(
+    menus: &mut Menus<W, K>,
+    refresh: bool,
+) -> IOResult<()> {
+    let (mounted, detached) = get_mounted_apps(refresh)?;
+    if mounted.is_empty() {
+        text!(menus, "No bind-mounted apps found");
+        return Ok(());
+    }
+    let to_detach: Vec<&str> = mounted
+        .iter()
+        .filter(|app| !detached.contains(app))
+        .map(String::as_str)
+        .collect();
+    if to_detach.is_empty() {
+        text!(
+            menus,
+            "All {} bind-mounted apps are already detached",
+            mounted.len()
+        );
+        return Ok(());
+    }
+    let Some(selected) = menus.checklist(
+        &to_detach,
+        "Bind-mounted apps to detach ('q' to leave):",
+        true,
+        Some(Key::Char('q')),
+    )?
+    else {
+        return Ok(());
+    };
+    let selected: Vec<&str> = selected.into_iter().map(|i| to_detach[i]).collect();
+    for pkg_name in write_detached(&selected, None)? {
+        textln!(menus, "{} {}", "detach:".success(), pkg_name);
+    }
+    for line in apply_report(None) {
+        textln!(menus, "{line}");
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 enum Op {
     DetachSelect,
     ReattachSelect,
+    AppManager,
+    ScanMounts,
     Reset,
     CopyToSd,
     Quit,
     Nop,
 }
 
-fn main_menu(menus: &mut Menus) -> IOResult<Op> {
+fn main_menu<W: Write, K: KeySource>(menus: &mut Menus<W, K>) -> IOResult<Op> {
     struct OpText {
         desc: &'static str,
         op: Op,
@@ -382,6 +1471,8 @@ fn main_menu(menus: &mut Menus) -> IOResult<Op> {
     let ops = [
         OpText::new("Detach", Op::DetachSelect),
         OpText::new("Re-attach", Op::ReattachSelect),
+        OpText::new("App manager (full-screen)", Op::AppManager),
+        OpText::new("Detach bind-mounted apps", Op::ScanMounts),
         OpText::new("Reset detached apps", Op::Reset),
         OpText::new("Copy detach.bin to /sdcard", Op::CopyToSd),
     ];
@@ -402,121 +1493,668 @@ fn main_menu(menus: &mut Menus) -> IOResult<Op> {
     }
 }
 
-fn bin_serialize(app: &str, f: &mut File) -> IOResult<()> {
-    let mut w = Vec::with_capacity(2 * app.len() - 1);
-    for b in app.as_bytes()[..app.len() - 1].iter().cloned() {
-        w.push(b);
-        w.push(0);
-    }
-    w.push(app.as_bytes()[app.len() - 1]);
+fn bin_serialize(app: &str, user: Option<u32>, f: &mut File) -> IOResult<()> {
+    let record = app_record(app, user)?;
     let mut f = BufWriter::new(f);
-    f.write_all(&[w
-        .len()
-        .try_into()
-        .expect("app name cannot be longer than 255")])?;
-    f.write_all(&w)?;
+    f.write_all(&record)?;
     f.flush()?;
     Ok(())
 }
 
-fn detach_menu(menus: &mut Menus) -> IOResult<()> {
-    let installed_apps = get_installed_apps()?;
-    assert_ne!(installed_apps.len(), 0);
-    let apps: Vec<&str> = installed_apps[..installed_apps.len() - 1]
-        .split(|&e| e == b'\n')
-        .map(|e| {
-            e.get("package:".len()..)
-                .expect("unexpected output from pm")
-        })
-        .map(|e| std::str::from_utf8(e).expect("non utf-8 package names?"))
-        .collect();
+/// The record of `app` detached for `user`, or for every user if `None`
+fn app_record(app: &str, user: Option<u32>) -> io::Result<Vec<u8>> {
+    match user {
+        Some(user) => {
+            let mut prefix = vec![TAG_USER_APP];
+            prefix.extend_from_slice(&user.to_le_bytes());
+            bin_record(app, &prefix)
+        }
+        None => bin_record(app, &[]),
+    }
+}
+
+/// `[len][prefix][name]`, the name's bytes interleaved with 0s like UTF-16 minus the last 0
+fn bin_record(name: &str, prefix: &[u8]) -> io::Result<Vec<u8>> {
+    let Some((&last, rest)) = name.as_bytes().split_last() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "empty package name",
+        ));
+    };
+    let mut w = Vec::with_capacity(1 + prefix.len() + 2 * name.len() - 1);
+    w.push(0);
+    w.extend_from_slice(prefix);
+    for &b in rest {
+        w.push(b);
+        w.push(0);
+    }
+    w.push(last);
+    w[0] = (w.len() - 1).try_into().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{name}' is too long for detach.bin"),
+        )
+    })?;
+    Ok(w)
+}
+
+/// A search result of the detach menu
+#[derive(Clone, PartialEq)]
+struct SearchResult<'a> {
+    app: fuzzy::Highlighted<'a>,
+    detached: bool,
+}
+
+impl Display for SearchResult<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.app)?;
+        if self.detached {
+            write!(f, " {}", "(detached)".success())?;
+        }
+        Ok(())
+    }
+}
+
+fn detach_menu<W: Write, K: KeySource>(
+    menus: &mut Menus<W, K>,
+    installed_apps: &[u8],
+) -> IOResult<()> {
+    let apps = parse_installed_apps(installed_apps);
+    assert_ne!(apps.len(), 0);
+    let detached_names: Vec<String> =
+        get_detached_apps(&fs::read(MODULE_DETACH).unwrap_or_default())
+            .into_iter()
+            .filter(|app| app.user.is_none())
+            .map(|app| app.name)
+            .collect();
     menus.cursor_show()?;
-    let col = terminal_size().expect("could not get terminal size").0 as usize - 2;
-    let selected = menus.select_menu_with_input(
+    let selected = menus.checklist_with_input(
         |input| {
             let input = input.trim();
-            if !input.is_empty() {
-                apps.iter()
-                    .filter(|app| {
-                        app.to_ascii_lowercase()
-                            .contains(&input.to_ascii_lowercase())
-                    })
-                    .take(5)
-                    .map(|s| &s[..col.min(s.len())])
-                    .collect()
-            } else {
-                Vec::new()
+            if input.is_empty() {
+                return Vec::new();
             }
+            let mut matches: Vec<_> = apps
+                .iter()
+                .filter_map(|app| Some((fuzzy::fuzzy_match(input, app.name)?, app)))
+                .collect();
+            // stable, pm order is kept among equals
+            matches.sort_by_key(|(m, app)| (std::cmp::Reverse(m.score), !app.is_user_installed()));
+            matches
+                .into_iter()
+                .map(|(m, app)| SearchResult {
+                    app: fuzzy::Highlighted {
+                        text: app.name,
+                        positions: m.positions,
+                    },
+                    detached: detached_names.iter().any(|d| d == app.name),
+                })
+                .collect()
         },
-        "↪".green(),
-        "- app: ",
+        &format!("- app ({} detached): ", detached_names.len()),
         None,
     )?;
     menus.cursor_hide()?;
-    if let Some(detach_app) = selected {
-        if detach_by_name(detach_app)? {
-            textln!(menus, "{} {}", "detach:".green(), detach_app);
-            textln!(menus, "Changes are applied. No need for a reboot!");
-        } else {
-            textln!(menus, "{} {}", "already detached:".green(), detach_app);
+    let Some(selected) = selected else {
+        return Ok(());
+    };
+    let (already, to_detach): (Vec<_>, Vec<_>) = selected.iter().partition(|r| r.detached);
+    let to_detach: Vec<&str> = to_detach.iter().map(|r| r.app.text).collect();
+    let mut to_reattach: Vec<&str> = already.iter().map(|r| r.app.text).collect();
+    if !to_reattach.is_empty() {
+        let question = match to_reattach.as_slice() {
+            [app] => format!("{app} is already detached. Re-attach it?"),
+            apps => format!(
+                "{} selected apps are already detached. Re-attach them?",
+                apps.len()
+            ),
+        };
+        if !menus.confirm(question)? {
+            to_reattach.clear();
+        }
+    }
+    let detached = write_detached(&to_detach, None)?;
+    let reattached = if to_reattach.is_empty() {
+        Vec::new()
+    } else {
+        remove_detached(&to_reattach, None)?
+    };
+    for app in &detached {
+        textln!(menus, "{} {}", "detach:".success(), app);
+    }
+    for app in &reattached {
+        textln!(menus, "{} {}", "re-attach:".danger(), app);
+    }
+    if !detached.is_empty() || !reattached.is_empty() {
+        for line in apply_report(None) {
+            textln!(menus, "{line}");
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the apps detached for every user with `apps`, the ones of single users stay
+fn replace_detached(apps: &[String]) -> IOResult<()> {
+    let content = match fs::read(MODULE_DETACH) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut records = Vec::new();
+    for app in apps {
+        records.extend(app_record(app, None)?);
+    }
+    for app in get_detached_apps(&content) {
+        if app.user.is_some() {
+            records.extend_from_slice(&content[app.range]);
         }
     }
+    fs::write(MODULE_DETACH, records)?;
     Ok(())
 }
 
-fn detach_by_name(detach_app: &str) -> IOResult<bool> {
+/// Adds `apps` not detached yet for `user` without notifying the store, returns the added ones
+/// Detaching an app for every user drops the records of it for single users
+fn write_detached<'a>(apps: &[&'a str], user: Option<u32>) -> IOResult<Vec<&'a str>> {
+    let mut content = match fs::read(MODULE_DETACH) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let detached_apps = get_detached_apps(&content);
+    let mut detached = Vec::new();
+    let mut records = Vec::new();
+    for &app in apps {
+        if detached.contains(&app)
+            || detached_apps
+                .iter()
+                .any(|d| d.name == app && d.applies_to(user))
+        {
+            continue;
+        }
+        records.extend(app_record(app, user)?);
+        detached.push(app);
+    }
+    let mut collapsed = Vec::new();
+    if user.is_none() {
+        // drain from the back so the earlier ranges stay valid
+        for d in detached_apps.iter().rev() {
+            if let Some(user) = d.user
+                && apps.contains(&d.name.as_str())
+            {
+                content.drain(d.range.clone());
+                collapsed.push((d.name.as_str(), user));
+            }
+        }
+    }
+    if collapsed.is_empty() {
+        let mut f = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(MODULE_DETACH)?;
+        f.write_all(&records)?;
+    } else {
+        content.extend(records);
+        fs::write(MODULE_DETACH, content)?;
+        for (app, user) in collapsed {
+            forget_detach_dates(&[app], Some(user))?;
+        }
+    }
+    record_detach_dates(&detached, user)?;
+    Ok(detached)
+}
+
+/// Lines of `<unix time> <package> [user]` in DETACH_DATES, only read by the app manager
+fn record_detach_dates(apps: &[&str], user: Option<u32>) -> IOResult<()> {
+    if apps.is_empty() {
+        return Ok(());
+    }
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
     let mut f = fs::OpenOptions::new()
         .create(true)
         .append(true)
-        .read(true)
-        .open(MODULE_DETACH)?;
-    let mut buf: Vec<u8> = Vec::new();
-    f.read_to_end(&mut buf)?;
-    if !get_detached_apps(&buf).iter().any(|(s, _)| s == detach_app) {
-        bin_serialize(detach_app, &mut f)?;
-        detach_bin_changed();
-        Ok(true)
-    } else {
-        Ok(false)
+        .open(DETACH_DATES)?;
+    let mut lines = String::new();
+    for app in apps {
+        match user {
+            Some(user) => lines.push_str(&format!("{now} {app} {user}\n")),
+            None => lines.push_str(&format!("{now} {app}\n")),
+        }
     }
+    f.write_all(lines.as_bytes())?;
+    Ok(())
 }
 
-fn _kill_store_am() -> IOResult<()> {
-    Command::new("am")
-        .args(["force-stop", "com.android.vending"])
-        .spawn()?
-        .wait()?;
+fn forget_detach_dates(apps: &[&str], user: Option<u32>) -> IOResult<()> {
+    let dates = match fs::read_to_string(DETACH_DATES) {
+        Ok(dates) => dates,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    let kept: String = dates
+        .lines()
+        .filter(|l| {
+            let mut fields = l.split(' ').skip(1);
+            let (app, u) = (fields.next(), fields.next().and_then(|u| u.parse().ok()));
+            !(app.is_some_and(|app| apps.contains(&app)) && u == user)
+        })
+        .flat_map(|l| [l, "\n"])
+        .collect();
+    fs::write(DETACH_DATES, kept)?;
     Ok(())
 }
 
-fn kill_store() -> IOResult<()> {
-    unsafe extern "C" {
-        fn kill(pid: i32, sig: i32) -> i32;
+/// When `app` was detached for `user`, as unix time
+fn get_detach_date(app: &str, user: Option<u32>) -> Option<u64> {
+    let dates = fs::read_to_string(DETACH_DATES).ok()?;
+    dates.lines().rev().find_map(|l| {
+        let mut fields = l.split(' ');
+        let time = fields.next()?.parse().ok()?;
+        let u = fields.next().filter(|&a| a == app).map(|_| fields.next())?;
+        (u.and_then(|u| u.parse().ok()) == user).then_some(time)
+    })
+}
+
+/// The stores of the config, the Play Store if it is unreadable
+/// A broken config must not break the commands the WebUI runs, it is warned about and ignored
+fn load_config() -> Config {
+    Config::load(CONFIG).unwrap_or_else(|e| {
+        eprintln!("WARNING: '{CONFIG}': {e}");
+        Config::default()
+    })
+}
+
+/// Read from the config on first use, once per run
+fn target_stores() -> Vec<&'static Store> {
+    static TARGETS: OnceLock<Vec<&'static Store>> = OnceLock::new();
+    TARGETS
+        .get_or_init(|| {
+            stores::targets(&load_config()).unwrap_or_else(|e| {
+                eprintln!("WARNING: '{CONFIG}': {e}, targeting the Play Store only");
+                vec![&stores::STORES[0]]
+            })
+        })
+        .clone()
+}
+
+/// Stops the target stores of `user`, or of every user if `None`, so they load detach.bin again
+fn kill_store(user: Option<u32>) -> IOResult<Vec<Stop>> {
+    let mut stops = Vec::new();
+    for store in target_stores() {
+        stops.push(process::stop(store, user)?);
     }
+    Ok(stops)
+}
 
-    const PKG: &[u8] = b"com.android.vending";
-    let mut buf = [0u8; PKG.len()];
-    for proc in fs::read_dir("/proc")? {
-        let mut proc = proc?.path();
-        if !proc.is_dir() {
-            continue;
+/// Notifies the stores of the changes, one line per store and whether a reboot is needed
+fn apply_report(user: Option<u32>) -> Vec<String> {
+    let stops = detach_bin_changed(user);
+    if NO_KILL.load(Ordering::Relaxed) {
+        return Vec::new();
+    }
+    stop_report(stops).0
+}
+
+fn stop_report(stops: IOResult<Vec<Stop>>) -> (Vec<String>, bool) {
+    let mut lines = Vec::new();
+    let applied = match stops {
+        Ok(stops) => {
+            lines.extend(stops.iter().map(|s| s.to_string()));
+            stops.iter().all(Stop::applied)
         }
-        proc.push("cmdline");
-        let Ok(mut cmdline) = fs::OpenOptions::new().read(true).open(&proc) else {
-            continue;
-        };
-        if !cmdline.read(&mut buf).is_ok_and(|n| n > 0) {
-            continue;
+        Err(e) => {
+            lines.push(format!("Could not stop the store: {e}"));
+            false
         }
-        if buf.eq(PKG) {
-            if let Some(pid) = proc.components().nth(2) {
-                let pid = pid.as_os_str().to_string_lossy();
-                let Ok(pid) = pid.parse::<i32>() else {
-                    continue;
-                };
-                unsafe { kill(pid, 9) };
-            }
+    };
+    if applied {
+        lines.push("Changes are applied. No need for a reboot!".to_string());
+    }
+    (lines, applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Mutex, MutexGuard, Once};
+
+    const COLS: u16 = 48;
+    const ROWS: u16 = 12;
+    const INSTALLED: &[u8] = b"\
+package:/data/app/~~a==/com.app1-b==/base.apk=com.app1
+package:/system/app/xxx2/xxx2.apk=org.xxx2
+package:/data/app/~~c==/com.apppppppp.tooolonngggg-d==/base.apk=com.apppppppp.tooolonnggggtooolonnggggtooolonngggg
+";
+
+    type TestMenus = Menus<vt::Screen, std::vec::IntoIter<Key>>;
+
+    fn menus(keys: Vec<Key>) -> TestMenus {
+        let screen = vt::Screen::new(COLS, ROWS);
+        Menus::with_io(screen, keys.into_iter(), Some((COLS, ROWS)))
+    }
+
+    fn typed(s: &str) -> Vec<Key> {
+        s.chars().map(Key::Char).collect()
+    }
+
+    fn screen(menus: &TestMenus) -> String {
+        menus.stdout.text()
+    }
+
+    fn out_of_keys<T: Debug>(r: IOResult<T>) {
+        match r {
+            Err(e) => assert_eq!(e.source.kind(), io::ErrorKind::UnexpectedEof),
+            Ok(v) => panic!("expected to run out of keys, got {v:?}"),
         }
     }
-    Ok(())
+
+    /// The module files are relative paths on linux, the flows run in a scratch
+    /// directory one at a time with an empty detach.bin
+    pub(crate) fn scratch_dir() -> MutexGuard<'static, ()> {
+        static LOCK: Mutex<()> = Mutex::new(());
+        static CHDIR: Once = Once::new();
+        let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        CHDIR.call_once(|| {
+            let dir =
+                std::env::temp_dir().join(format!("zygisk-detach-test-{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            std::env::set_current_dir(dir).unwrap();
+        });
+        let _ = fs::remove_file(MODULE_DETACH);
+        let _ = fs::remove_file(DETACH_DATES);
+        guard
+    }
+
+    #[test]
+    fn main_menu_layout() {
+        let mut m = menus(vec![]);
+        out_of_keys(main_menu(&mut m));
+        assert_eq!(
+            screen(&m),
+            "\
+- Selection:
+1. Detach
+2. Re-attach
+3. App manager (full-screen)
+4. Detach bind-mounted apps
+5. Reset detached apps
+6. Copy detach.bin to /sdcard
+q. Quit"
+        );
+    }
+
+    #[test]
+    fn main_menu_selects_by_number() {
+        let mut m = menus(vec![Key::Char('2')]);
+        assert_eq!(main_menu(&mut m).unwrap(), Op::ReattachSelect);
+        let mut m = menus(vec![Key::Char('q')]);
+        assert_eq!(main_menu(&mut m).unwrap(), Op::Quit);
+        // the menu is cleared once a key is read
+        assert_eq!(screen(&m), "");
+    }
+
+    #[test]
+    fn main_menu_undefined_key() {
+        let mut m = menus(vec![Key::Char('x')]);
+        assert_eq!(main_menu(&mut m).unwrap(), Op::Nop);
+        assert_eq!(screen(&m), "Undefined key 'x'");
+    }
+
+    #[test]
+    fn labels_are_cut_by_columns() {
+        // CJK takes two columns, the ellipsis one
+        assert_eq!(menus::truncate("카카오톡 app", 12), "카카오톡 app");
+        assert_eq!(menus::truncate("카카오톡 app", 8), "카카오…");
+        assert_eq!(menus::truncate("카카오톡 app", 6), "카카…");
+        // no half of a wide char
+        assert_eq!(menus::truncate("카카오톡 app", 5), "카카…");
+        // escape sequences take none
+        assert_eq!(
+            menus::truncate("\x1b[1m카카오톡\x1b[0m", 8),
+            "\x1b[1m카카오톡\x1b[0m"
+        );
+        assert_eq!(menus::truncate("\x1b[1m카카오톡 app", 6), "\x1b[1m카카…");
+    }
+
+    #[test]
+    fn detach_menu_search() {
+        let _dir = scratch_dir();
+        let mut m = menus(typed("app"));
+        out_of_keys(detach_menu(&mut m, INSTALLED));
+        assert_eq!(
+            screen(&m),
+            "\
+- app (0 detached): app
+
+2 found  ↑/↓ PGUP/PGDN to navigate
+SPACE toggle, 'a' all, ENTER apply (0 selected)
+[ ] com.app1
+[ ] com.apppppppp.tooolonnggggtooolonnggggtooo…"
+        );
+        assert_eq!(m.stdout.cursor, (23, 0));
+    }
+
+    #[test]
+    fn detach_menu_line_editing() {
+        let _dir = scratch_dir();
+        let mut keys = typed("com.xx");
+        keys.extend([Key::Ctrl('w'), Key::Home, Key::Delete]);
+        let mut m = menus(keys);
+        out_of_keys(detach_menu(&mut m, INSTALLED));
+        assert!(screen(&m).starts_with("- app (0 detached): om."));
+        assert_eq!(m.stdout.cursor, (20, 0));
+    }
+
+    #[test]
+    fn detach_menu_detaches_checked() {
+        let _dir = scratch_dir();
+        let mut keys = typed("app");
+        keys.extend([
+            Key::Down,
+            Key::Char(' '),
+            Key::Down,
+            Key::Char(' '),
+            Key::Char('\n'),
+        ]);
+        let mut m = menus(keys);
+        detach_menu(&mut m, INSTALLED).unwrap();
+        assert_eq!(
+            screen(&m),
+            "\
+detach: com.app1
+detach: com.apppppppp.tooolonnggggtooolonnggggto
+oolonngggg
+Google Play Store: not running
+Changes are applied. No need for a reboot!"
+        );
+        assert_eq!(
+            get_detached_names().unwrap(),
+            [
+                "com.app1",
+                "com.apppppppp.tooolonnggggtooolonnggggtooolonngggg"
+            ]
+        );
+    }
+
+    #[test]
+    fn detach_menu_marks_detached() {
+        let _dir = scratch_dir();
+        write_detached(&["com.app1"], None).unwrap();
+        let mut keys = typed("app1");
+        keys.extend([Key::Char('\n'), Key::Char('y')]);
+        let mut m = menus(keys);
+        detach_menu(&mut m, INSTALLED).unwrap();
+        assert_eq!(
+            screen(&m),
+            "\
+re-attach: com.app1
+Google Play Store: not running
+Changes are applied. No need for a reboot!"
+        );
+        assert!(get_detached_names().unwrap().is_empty());
+
+        write_detached(&["com.app1"], None).unwrap();
+        let mut m = menus(typed("app1"));
+        out_of_keys(detach_menu(&mut m, INSTALLED));
+        assert_eq!(
+            screen(&m),
+            "\
+- app (1 detached): app1
+
+1 found  ↑/↓ PGUP/PGDN to navigate
+SPACE toggle, 'a' all, ENTER apply (0 selected)
+[ ] com.app1 (detached)"
+        );
+    }
+
+    #[test]
+    fn scan_mounts_menu_preselects_the_mounted_apps() {
+        let _dir = scratch_dir();
+        fs::write(
+            MOUNTINFO,
+            "\
+21 1 253:4 / / ro,relatime shared:1 - ext4 /dev/block/dm-4 ro,seclabel
+1405 58 259:9 /adb/a.apk /data/app/~~aaa==/com.app1-bbb==/base.apk ro shared:35 - f2fs /dev/block/dm-7 rw
+1406 58 259:9 /adb/b.apk /data/app/org.xxx2-1/base.apk ro shared:35 - f2fs /dev/block/dm-7 rw
+",
+        )
+        .unwrap();
+        let mut m = menus(vec![]);
+        out_of_keys(scan_mounts_menu(&mut m, false));
+        assert_eq!(
+            screen(&m),
+            "\
+Bind-mounted apps to detach ('q' to leave):
+SPACE toggle, 'a' all, ENTER apply
+[x] com.app1
+[x] org.xxx2
+1/2"
+        );
+
+        let mut m = menus(vec![Key::Char(' '), Key::Char('\n')]);
+        scan_mounts_menu(&mut m, true).unwrap();
+        assert_eq!(get_detached_names().unwrap(), ["org.xxx2"]);
+        fs::remove_file(MOUNTINFO).unwrap();
+    }
+
+    #[test]
+    fn reattach_menu_layout() {
+        let _dir = scratch_dir();
+        write_detached(&["com.app1", "org.xxx2"], None).unwrap();
+        write_detached(&["com.app3"], Some(10)).unwrap();
+        let mut m = menus(vec![Key::Down]);
+        out_of_keys(reattach_menu(&mut m));
+        assert_eq!(
+            screen(&m),
+            "\
+Select the apps to re-attach ('q' to leave):
+SPACE toggle, 'a' all, ENTER apply
+[ ] com.app1
+[ ] org.xxx2
+[ ] com.app3 (user 10)
+2/3"
+        );
+    }
+
+    #[test]
+    fn reattach_menu_scrolls() {
+        let _dir = scratch_dir();
+        let apps: Vec<String> = (0..20).map(|i| format!("com.app{i}")).collect();
+        let apps: Vec<&str> = apps.iter().map(String::as_str).collect();
+        write_detached(&apps, None).unwrap();
+        let mut m = menus(vec![Key::PageDown, Key::Down, Key::Char(' ')]);
+        out_of_keys(reattach_menu(&mut m));
+        assert_eq!(
+            screen(&m),
+            "\
+Select the apps to re-attach ('q' to leave):
+SPACE toggle, 'a' all, ENTER apply
+[ ] com.app2
+[ ] com.app3
+[ ] com.app4
+[ ] com.app5
+[ ] com.app6
+[ ] com.app7
+[ ] com.app8
+[x] com.app9
+10/20"
+        );
+    }
+
+    #[test]
+    fn reattach_menu_removes_checked() {
+        let _dir = scratch_dir();
+        write_detached(&["com.app1", "org.xxx2"], None).unwrap();
+        let mut m = menus(vec![Key::Char(' '), Key::Char('\n')]);
+        reattach_menu(&mut m).unwrap();
+        assert_eq!(
+            screen(&m),
+            "\
+re-attach: com.app1
+Google Play Store: not running
+Changes are applied. No need for a reboot!"
+        );
+        assert_eq!(get_detached_names().unwrap(), ["org.xxx2"]);
+    }
+
+    #[test]
+    fn reattach_menu_without_detach_bin() {
+        let _dir = scratch_dir();
+        let mut m = menus(vec![]);
+        reattach_menu(&mut m).unwrap();
+        assert_eq!(screen(&m), "detach.bin not found");
+    }
+
+    #[test]
+    fn detaching_for_every_user_collapses_user_records() {
+        let _dir = scratch_dir();
+        write_detached(&["com.app1", "org.xxx2"], Some(10)).unwrap();
+        write_detached(&["com.app1"], Some(11)).unwrap();
+        assert_eq!(write_detached(&["com.app1"], None).unwrap(), ["com.app1"]);
+        let content = fs::read(MODULE_DETACH).unwrap();
+        let detached: Vec<String> = get_detached_apps(&content)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(detached, ["org.xxx2 (user 10)", "com.app1"]);
+    }
+
+    #[test]
+    fn detachall_keeps_user_records() {
+        let _dir = scratch_dir();
+        write_detached(&["com.app1"], None).unwrap();
+        write_detached(&["com.app3"], Some(10)).unwrap();
+        replace_detached(&["org.xxx2".to_string()]).unwrap();
+        let content = fs::read(MODULE_DETACH).unwrap();
+        let detached: Vec<String> = get_detached_apps(&content)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(detached, ["org.xxx2", "com.app3 (user 10)"]);
+        // nothing is written if a name does not fit
+        assert!(replace_detached(&["a".repeat(200)]).is_err());
+        assert_eq!(fs::read(MODULE_DETACH).unwrap(), content);
+    }
+
+    #[test]
+    fn batch_lines_do_not_read_stdin() {
+        let _dir = scratch_dir();
+        IN_BATCH.store(true, Ordering::Relaxed);
+        let status = run("parcel", vec!["decode".to_string()].into_iter(), false);
+        IN_BATCH.store(false, Ordering::Relaxed);
+        assert_eq!(status, ExitCode::FAILURE);
+    }
+
+    #[test]
+    fn long_names_do_not_fit_a_record() {
+        let name = "a".repeat(125);
+        assert_eq!(app_record(&name, Some(10)).unwrap().len(), 1 + 5 + 249);
+        let name = "a".repeat(126);
+        assert!(app_record(&name, None).is_ok());
+        let err = app_record(&name, Some(10)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(bin_record("", &[]).is_err());
+    }
 }
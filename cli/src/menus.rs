@@ -1,9 +1,15 @@
 use crate::colorize::ToColored;
+use crate::line_edit::{self, LineEdit};
+use std::collections::VecDeque;
 use std::fmt::Display;
-use std::io::{self, BufWriter, StdoutLock, Write};
-use termion::input::TermRead;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, StdoutLock, Write};
+use std::mem::ManuallyDrop;
+use std::os::fd::FromRawFd;
+use termion::event::{Event, Key};
 use termion::raw::{IntoRawMode, RawTerminal};
-use termion::{clear, cursor, event::Key};
+use termion::{clear, cursor, terminal_size};
+use unicode_width::UnicodeWidthChar;
 
 #[macro_export]
 macro_rules! text {
@@ -32,16 +38,159 @@ pub enum SelectNumberedResp {
     UndefinedKey(Key),
     Quit,
 }
-pub struct Menus {
-    pub(crate) stdout: BufWriter<RawTerminal<StdoutLock<'static>>>,
+/// Where the menus read keys from, the terminal or a script in the tests
+pub trait KeySource {
+    fn next_key(&mut self) -> io::Result<Key>;
+
+    /// Waits up to `timeout` ms (forever if negative) and returns every key that came in,
+    /// an empty list on timeout or on a signal, `None` once the input is closed
+    fn poll_keys(&mut self, _timeout: i32) -> io::Result<Option<Vec<Key>>> {
+        match self.next_key() {
+            Ok(key) => Ok(Some(vec![key])),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Keys are waiting to be read
+    fn has_pending(&mut self) -> bool {
+        false
+    }
+}
+
+const POLLIN: i16 = 0x1;
+const POLLERR: i16 = 0x8;
+const POLLNVAL: i16 = 0x20;
+
+#[repr(C)]
+struct PollFd {
+    fd: i32,
+    events: i16,
+    revents: i16,
+}
+
+#[cfg(target_os = "android")]
+type NFds = std::ffi::c_uint;
+#[cfg(target_os = "linux")]
+type NFds = std::ffi::c_ulong;
+
+unsafe extern "C" {
+    fn poll(fds: *mut PollFd, nfds: NFds, timeout: i32) -> i32;
+}
+
+/// The terminal. Reads fd 0 itself rather than through `io::stdin`, whose buffer `poll`
+/// cannot see, and keeps what it read past a key for the next one.
+#[derive(Default)]
+pub struct StdinKeys {
+    keys: VecDeque<Key>,
+}
+
+impl StdinKeys {
+    fn poll_stdin(timeout: i32) -> io::Result<bool> {
+        let mut fd = PollFd {
+            fd: 0,
+            events: POLLIN,
+            revents: 0,
+        };
+        if unsafe { poll(&mut fd, 1, timeout) } <= 0 {
+            return Ok(false);
+        }
+        if fd.revents & (POLLERR | POLLNVAL) != 0 {
+            return Err(io::Error::other("stdin is not readable"));
+        }
+        Ok(true)
+    }
+
+    /// Queues the keys of one read of stdin, `false` once it is closed
+    fn read(&mut self, timeout: i32) -> io::Result<bool> {
+        if !Self::poll_stdin(timeout)? {
+            return Ok(true);
+        }
+        let mut stdin = ManuallyDrop::new(unsafe { File::from_raw_fd(0) });
+        let mut buf = [0u8; 256];
+        let n = stdin.read(&mut buf)?;
+        // a POLLHUP without anything left to read
+        if n == 0 {
+            return Ok(false);
+        }
+        let mut bytes = buf[..n].iter().map(|&b| Ok(b));
+        while let Some(Ok(b)) = bytes.next() {
+            if b == 0x1b && bytes.len() == 0 {
+                self.keys.push_back(Key::Esc);
+                continue;
+            }
+            if let Ok(Event::Key(k)) = termion::event::parse_event(b, &mut bytes) {
+                self.keys.push_back(k);
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl KeySource for StdinKeys {
+    fn next_key(&mut self) -> io::Result<Key> {
+        loop {
+            if let Some(key) = self.keys.pop_front() {
+                return Ok(key);
+            }
+            if !self.read(-1)? {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+
+    fn poll_keys(&mut self, timeout: i32) -> io::Result<Option<Vec<Key>>> {
+        if self.keys.is_empty() && !self.read(timeout)? {
+            return Ok(None);
+        }
+        Ok(Some(self.keys.drain(..).collect()))
+    }
+
+    fn has_pending(&mut self) -> bool {
+        !self.keys.is_empty() || Self::poll_stdin(0).unwrap_or(false)
+    }
+}
+
+/// Scripted keys, running out of them is an `UnexpectedEof`
+impl<I: Iterator<Item = Key>> KeySource for I {
+    fn next_key(&mut self) -> io::Result<Key> {
+        self.next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "out of keys"))
+    }
+}
+
+pub type Terminal = BufWriter<RawTerminal<StdoutLock<'static>>>;
+
+pub struct Menus<W: Write = Terminal, K: KeySource = StdinKeys> {
+    pub(crate) stdout: W,
+    pub(crate) keys: K,
+    /// columns and rows, the terminal's if `None`
+    size: Option<(u16, u16)>,
+    /// searches of this session, recalled with UP in the prompts
+    history: Vec<String>,
 }
 impl Menus {
-    pub fn new() -> Self {
+    pub fn new() -> io::Result<Self> {
+        crate::term::save();
+        let stdout = BufWriter::new(io::stdout().lock().into_raw_mode()?);
+        Ok(Self::with_io(stdout, StdinKeys::default(), None))
+    }
+}
+impl<W: Write, K: KeySource> Menus<W, K> {
+    pub fn with_io(stdout: W, keys: K, size: Option<(u16, u16)>) -> Self {
         Self {
-            stdout: BufWriter::new(io::stdout().lock().into_raw_mode().unwrap()),
+            stdout,
+            keys,
+            size,
+            history: Vec::new(),
         }
     }
 
+    pub(crate) fn size(&self) -> (u16, u16) {
+        self.size
+            .unwrap_or_else(|| terminal_size().unwrap_or((80, 24)))
+    }
+
     pub fn cursor_hide(&mut self) -> io::Result<()> {
         write!(self.stdout, "{}", cursor::Hide)?;
         Ok(())
@@ -52,164 +201,228 @@ impl Menus {
         Ok(())
     }
 
-    pub fn select_menu<L: Display, I: Iterator<Item = L> + Clone>(
+    /// Rows left for a list when `reserved` rows are taken by the rest of the menu
+    fn list_height(&self, reserved: usize) -> usize {
+        let (_, rows) = self.size();
+        (rows as usize).saturating_sub(reserved).max(1)
+    }
+
+    fn list_width(&self, prefix: usize) -> usize {
+        let (cols, _) = self.size();
+        (cols as usize).saturating_sub(prefix + 1).max(1)
+    }
+
+    /// Returns the indices of the checked items, or the highlighted one if none is checked
+    pub fn checklist<L: Display>(
         &mut self,
-        list: I,
+        list: &[L],
         title: impl Display,
-        prompt: impl Display,
+        all_checked: bool,
         quit: Option<Key>,
-    ) -> io::Result<Option<usize>> {
+    ) -> io::Result<Option<Vec<usize>>> {
         let mut select_idx = 0;
-        let list_len = list.clone().count();
-        let mut keys = io::stdin().lock().keys();
+        let mut top = 0;
+        let mut checked = vec![all_checked; list.len()];
         write!(self.stdout, "{}\r\n", title)?;
+        write!(
+            self.stdout,
+            "{}\r\n",
+            "SPACE toggle, 'a' all, ENTER apply".faint()
+        )?;
         let ret = loop {
-            for (i, selection) in list.clone().enumerate() {
+            // title, hint and position lines
+            let height = self.list_height(4);
+            let width = self.list_width(4);
+            top = scroll(top, select_idx, height);
+            let shown = top..list.len().min(top + height);
+            for i in shown.clone() {
+                let mark = if checked[i] { "[x]" } else { "[ ]" };
+                let selection = truncate(&list[i].to_string(), width);
                 if i == select_idx {
                     write!(
                         self.stdout,
                         "{} {}\r\n",
-                        prompt,
-                        selection.black().white_bg()
+                        mark.success(),
+                        selection.selected()
                     )?;
                 } else {
-                    write!(self.stdout, "{}\r\n", selection.faint())?;
+                    write!(self.stdout, "{} {}\r\n", mark, selection.faint())?;
                 }
             }
+            write!(self.stdout, "{}", position(select_idx, list.len()).faint())?;
             self.stdout.flush()?;
 
-            let key = keys
-                .next()
-                .expect("keys() should block")
-                .expect("faulty keyboard?");
+            let key = self.keys.next_key()?;
             write!(
                 self.stdout,
                 "\r{}{}",
-                cursor::Up(list_len as u16),
+                cursor::Up(shown.len() as u16),
                 clear::AfterCursor
             )?;
             match key {
                 Key::Char('\n') => {
-                    break Ok(Some(select_idx));
-                }
-                Key::Up => select_idx = select_idx.saturating_sub(1),
-                Key::Down => {
-                    if select_idx + 1 < list_len {
-                        select_idx += 1;
+                    if !checked.contains(&true) {
+                        checked[select_idx] = true;
                     }
+                    break Ok(Some((0..list.len()).filter(|&i| checked[i]).collect()));
+                }
+                Key::Char(' ') => checked[select_idx] = !checked[select_idx],
+                Key::Char('a') => {
+                    let all = !checked.contains(&false);
+                    checked.iter_mut().for_each(|c| *c = !all);
                 }
                 k if k == Key::Ctrl('c') || quit.is_some_and(|q| q == key) => {
                     break Ok(None);
                 }
-                _ => {}
+                k => {
+                    if let Some(i) = navigate(k, select_idx, list.len(), height) {
+                        select_idx = i;
+                    }
+                }
             }
         };
-        write!(self.stdout, "{}{}", cursor::Up(1), clear::CurrentLine)?;
+        write!(self.stdout, "{}{}", cursor::Up(2), clear::AfterCursor)?;
         self.stdout.flush()?;
         ret
     }
 
-    pub fn select_menu_with_input<F: Fn(&str) -> Vec<L>, L: Display>(
+    /// Search prompt with a checklist of results. The highlight starts on the prompt,
+    /// ↓ moves it into the list where SPACE toggles and 'a' checks every visible item.
+    /// Checked items are kept across searches.
+    /// Returns the checked items, or the highlighted (or first) result if none is checked.
+    pub fn checklist_with_input<F: Fn(&str) -> Vec<L>, L: Display + Clone + PartialEq>(
         &mut self,
         lister: F,
-        prompt: impl Display,
         input_prompt: &str,
         quit: Option<Key>,
-    ) -> io::Result<Option<L>> {
-        let mut select_idx = 0;
-        let mut cursor = 0;
-        let mut input = String::new();
+    ) -> io::Result<Option<Vec<L>>> {
+        let mut select_idx: Option<usize> = None;
+        let mut top = 0;
+        let mut line = LineEdit::default();
+        let mut checked: Vec<L> = Vec::new();
 
-        let mut keys = io::stdin().lock().keys();
         let ret = loop {
             write!(
                 self.stdout,
                 "\r{}{}{}",
                 clear::AfterCursor,
-                input_prompt.magenta(),
-                input,
+                input_prompt.prompt(),
+                line.text(),
             )?;
-            let mut list = lister(&input);
+            let list = lister(line.text());
             let list_len = list.len();
-
-            select_idx = select_idx.min(list_len);
-            if list_len > 0 {
-                write!(self.stdout, "\r\n\n↑ and ↓ to navigate")?;
-                write!(self.stdout, "\n\rENTER to select\r\n")?;
+            // input, blank and two hint lines
+            let height = self.list_height(5);
+            let width = self.list_width(4);
+            select_idx = select_idx.map(|i| i.min(list_len.saturating_sub(1)));
+            if list_len == 0 {
+                select_idx = None;
             }
+            top = scroll(top, select_idx.unwrap_or(0), height);
+            let shown = top..list_len.min(top + height);
 
-            for (i, selection) in list.iter().enumerate() {
-                if i == select_idx {
+            let hint_lines = if list_len > 0 || !checked.is_empty() {
+                let hint = format!(
+                    "{}  ↑/↓ PGUP/PGDN to navigate",
+                    match select_idx {
+                        Some(i) => position(i, list_len),
+                        None => format!("{list_len} found"),
+                    }
+                );
+                write!(self.stdout, "\r\n\n{}", truncate(&hint, width + 4))?;
+                let hint = format!(
+                    "SPACE toggle, 'a' all, ENTER apply ({} selected)",
+                    checked.len()
+                );
+                write!(self.stdout, "\n\r{}\r\n", truncate(&hint, width + 4))?;
+                4
+            } else {
+                0
+            };
+            for i in shown.clone() {
+                let mark = if checked.contains(&list[i]) {
+                    "[x]"
+                } else {
+                    "[ ]"
+                };
+                let selection = truncate(&list[i].to_string(), width);
+                if select_idx == Some(i) {
                     write!(
                         self.stdout,
                         "{} {}\r\n",
-                        prompt,
-                        selection.black().white_bg()
+                        mark.success(),
+                        selection.selected()
                     )?;
                 } else {
-                    write!(self.stdout, "{}\r\n", selection.faint())?;
+                    write!(self.stdout, "{} {}\r\n", mark, selection.faint())?;
                 }
             }
-            if list_len > 0 {
-                write!(self.stdout, "{}", cursor::Up(list_len as u16 + 4))?;
+            if hint_lines > 0 {
+                write!(
+                    self.stdout,
+                    "{}",
+                    cursor::Up(shown.len() as u16 + hint_lines)
+                )?;
             }
             write!(
                 self.stdout,
                 "\r{}",
-                cursor::Right(input_prompt.len() as u16 + cursor as u16)
+                cursor::Right((printable_len(input_prompt) + line.cursor_width()) as u16)
             )?;
             self.stdout.flush()?;
             write!(self.stdout, "\r{}", clear::AfterCursor)?;
 
-            match keys
-                .next()
-                .expect("keys() should block")
-                .expect("faulty keyboard?")
-            {
-                Key::Char('\n') => {
-                    break Ok(if list_len > select_idx {
-                        Some(list.remove(select_idx))
-                    } else {
-                        None
-                    });
-                }
-                Key::Up => select_idx = select_idx.saturating_sub(1),
-                Key::Down => {
-                    if select_idx + 1 < list_len {
-                        select_idx += 1;
+            let key = self.keys.next_key()?;
+            match (select_idx, key) {
+                (_, Key::Char('\n')) => {
+                    if checked.is_empty() {
+                        match list.into_iter().nth(select_idx.unwrap_or(0)) {
+                            Some(l) => checked.push(l),
+                            None => break Ok(None),
+                        }
                     }
+                    line_edit::remember(&mut self.history, line.text());
+                    break Ok(Some(checked));
                 }
-                Key::Backspace => {
-                    if cursor > 0 {
-                        cursor -= 1;
-                        input.remove(cursor);
-                    }
+                (None, Key::Up) if list_len == 0 || line.is_browsing_history() => {
+                    line.history_prev(&self.history)
                 }
-                Key::Char(c) => {
-                    if c.is_ascii() {
-                        input.insert(cursor, c);
-                        cursor += 1;
+                (None, Key::Down) if list_len == 0 && line.is_browsing_history() => {
+                    line.history_next(&self.history)
+                }
+                (None, Key::Down) if list_len > 0 => select_idx = Some(0),
+                (Some(0), Key::Up) => select_idx = None,
+                (Some(i), Key::Char(' ')) => {
+                    if let Some(pos) = checked.iter().position(|c| *c == list[i]) {
+                        checked.remove(pos);
                     } else {
-                        write!(
-                            self.stdout,
-                            "{}{}{}{}\r",
-                            cursor::Up(1),
-                            clear::CurrentLine,
-                            format_args!("Only ASCII characters"),
-                            cursor::Down(1)
-                        )?;
+                        checked.push(list[i].clone());
                     }
                 }
-                Key::Right => {
-                    if cursor < input.len() {
-                        cursor += 1
+                (Some(_), Key::Char('a')) => {
+                    if list.iter().all(|l| checked.contains(l)) {
+                        checked.retain(|c| !list.contains(c));
+                    } else {
+                        for l in list {
+                            if !checked.contains(&l) {
+                                checked.push(l);
+                            }
+                        }
                     }
                 }
-                Key::Left => cursor = cursor.saturating_sub(1),
-                k if k == Key::Ctrl('c') || quit.is_some_and(|q| q == k) => {
+                (Some(i), k) if navigate(k, i, list_len, height).is_some() => {
+                    select_idx = navigate(k, i, list_len, height);
+                }
+                (_, k) if k == Key::Ctrl('c') || quit.is_some_and(|q| q == k) => {
                     break Ok(None);
                 }
-                _ => {}
+                (_, key) => {
+                    // anything else edits the search
+                    select_idx = None;
+                    if key != Key::Char(' ') {
+                        line.edit(key);
+                    }
+                }
             }
         };
         write!(self.stdout, "\r{}{}\r\n", cursor::Up(1), clear::AfterCursor)?;
@@ -217,6 +430,17 @@ impl Menus {
         ret
     }
 
+    /// y/N question on the current line, cleared after the answer
+    pub fn confirm(&mut self, question: impl Display) -> io::Result<bool> {
+        let question = truncate(&question.to_string(), self.list_width(7));
+        write!(self.stdout, "\r{}{} (y/N) ", clear::CurrentLine, question)?;
+        self.stdout.flush()?;
+        let key = self.keys.next_key()?;
+        write!(self.stdout, "\r{}", clear::CurrentLine)?;
+        self.stdout.flush()?;
+        Ok(matches!(key, Key::Char('y' | 'Y')))
+    }
+
     pub fn select_menu_numbered<L: Display, I: Iterator<Item = L> + Clone>(
         &mut self,
         list: I,
@@ -226,16 +450,11 @@ impl Menus {
         let list_len = list.clone().count();
         write!(self.stdout, "\r{title}\r\n")?;
         for (i, s) in list.enumerate() {
-            write!(self.stdout, "{}. {}\r\n", (i + 1).green(), s)?;
+            write!(self.stdout, "{}. {}\r\n", (i + 1).success(), s)?;
         }
-        write!(self.stdout, "{}. Quit\r\n", 'q'.green())?;
+        write!(self.stdout, "{}. Quit\r\n", 'q'.success())?;
         self.stdout.flush()?;
-        let key = io::stdin()
-            .lock()
-            .keys()
-            .next()
-            .expect("keys() should block")
-            .expect("faulty keyboard?");
+        let key = self.keys.next_key()?;
         write!(
             self.stdout,
             "\r{}{}",
@@ -252,3 +471,83 @@ impl Menus {
         }
     }
 }
+
+/// List navigation shared by the menus.
+/// The new highlight index, or `None` if `key` does not navigate.
+pub(crate) fn navigate(key: Key, idx: usize, len: usize, page: usize) -> Option<usize> {
+    let last = len.saturating_sub(1);
+    Some(match key {
+        Key::Up => idx.saturating_sub(1),
+        Key::Down => (idx + 1).min(last),
+        Key::PageUp => idx.saturating_sub(page),
+        Key::PageDown => (idx + page).min(last),
+        Key::Home => 0,
+        Key::End => last,
+        _ => return None,
+    })
+}
+
+/// First row of a `height` tall viewport starting at `top`, moved just enough to show `idx`
+pub(crate) fn scroll(top: usize, idx: usize, height: usize) -> usize {
+    if idx < top {
+        idx
+    } else if idx >= top + height {
+        idx + 1 - height
+    } else {
+        top
+    }
+}
+
+pub(crate) fn position(idx: usize, len: usize) -> String {
+    format!("{}/{}", (idx + 1).min(len), len)
+}
+
+/// Cuts `s` to `width` columns, escape sequences are kept and not counted
+pub(crate) fn truncate(s: &str, width: usize) -> String {
+    if printable_len(s) <= width {
+        return s.to_string();
+    }
+    let mut t = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    let mut n = 0;
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // CSI: ESC [ params final-byte
+            t.push(c);
+            for p in chars.by_ref() {
+                t.push(p);
+                if p != '[' && ('@'..='~').contains(&p) {
+                    break;
+                }
+            }
+            continue;
+        }
+        let w = c.width().unwrap_or(0);
+        // leave room for the ellipsis
+        if n + w + 1 > width {
+            break;
+        }
+        t.push(c);
+        n += w;
+    }
+    t.push('…');
+    t
+}
+
+/// Columns `s` takes on the terminal, wide chars count twice
+fn printable_len(s: &str) -> usize {
+    let mut n = 0;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            for p in chars.by_ref() {
+                if p != '[' && ('@'..='~').contains(&p) {
+                    break;
+                }
+            }
+        } else {
+            n += c.width().unwrap_or(0);
+        }
+    }
+    n
+}
@@ -0,0 +1,120 @@
+/// Packages whose installed APK has something bind-mounted over it, in mountinfo order.
+/// `apk_paths` is the `pm list packages -f` output.
+pub fn mounted_apps(mountinfo: &str, apk_paths: &str) -> Vec<String> {
+    let installed: Vec<(&str, &str)> = apk_paths
+        .lines()
+        .filter_map(|l| l.strip_prefix("package:")?.rsplit_once('='))
+        .collect();
+    let mut apps = Vec::new();
+    for mount_point in mountinfo.lines().filter_map(mount_point) {
+        if !mount_point.starts_with("/data/app/") || !mount_point.ends_with(".apk") {
+            continue;
+        }
+        let Some(pkg) = installed
+            .iter()
+            .find(|(path, _)| *path == mount_point)
+            .map(|(_, pkg)| pkg.to_string())
+            .or_else(|| pkg_from_path(&mount_point))
+        else {
+            continue;
+        };
+        if !apps.contains(&pkg) {
+            apps.push(pkg);
+        }
+    }
+    apps
+}
+
+/// 5th field of a mountinfo line with the octal escapes ("\040" for space) undone
+fn mount_point(line: &str) -> Option<String> {
+    let field = line.split(' ').nth(4)?;
+    let mut out = Vec::with_capacity(field.len());
+    let bytes = field.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && let Some(c) = field
+                .get(i + 1..i + 4)
+                .and_then(|o| u8::from_str_radix(o, 8).ok())
+        {
+            out.push(c);
+            i += 4;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+/// `/data/app/[~~xxx==/]com.app-yyy==/base.apk` -> `com.app`
+fn pkg_from_path(path: &str) -> Option<String> {
+    let dir = path.rsplit('/').nth(1)?;
+    let (pkg, _) = dir.split_once('-')?;
+    Some(pkg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// /proc/self/mountinfo of a device with a Magisk module bind-mounting patched APKs
+    const MOUNTINFO: &str = r"21 1 253:4 / / ro,relatime shared:1 - ext4 /dev/block/dm-4 ro,seclabel
+24 21 0:22 / /proc rw,nosuid,nodev,noexec,relatime shared:11 - proc proc rw,gid=3009,hidepid=invisible
+58 21 259:9 / /data rw,nosuid,nodev,noatime shared:35 - f2fs /dev/block/dm-7 rw,lazytime,seclabel,background_gc=on
+1405 58 259:9 /adb/rvhc/youtube.apk /data/app/~~Xq3VbJ8d0ZsR9vQmP2w==/com.google.android.youtube-5Lr0wPb8f4RgqFhtmYw==/base.apk ro,nosuid,nodev,noatime shared:35 - f2fs /dev/block/dm-7 rw,lazytime,seclabel
+1406 58 259:9 /adb/rvhc/music.apk /data/app/~~b2VqM1z==/com.google.android.apps.youtube.music-Zm9v==/base.apk ro,nosuid,nodev,noatime shared:35 - f2fs /dev/block/dm-7 rw,lazytime,seclabel
+1407 58 259:9 /adb/rvhc/youtube.apk /data/app/~~Xq3VbJ8d0ZsR9vQmP2w==/com.google.android.youtube-5Lr0wPb8f4RgqFhtmYw==/base.apk ro,nosuid,nodev,noatime shared:35 - f2fs /dev/block/dm-7 rw,lazytime,seclabel
+1408 58 259:9 /adb/modules/x/my\040app.apk /data/app/com.example.spaced-1/my\040app.apk ro,relatime shared:35 - f2fs /dev/block/dm-7 rw
+1409 58 259:9 /adb/modules/x/lib.so /data/app/~~c2Q==/com.other-1==/lib/arm64/libx.so ro,relatime shared:35 - f2fs /dev/block/dm-7 rw
+1410 21 7:48 / /apex/com.android.art@340090000 ro,nodev,noatime - ext4 /dev/block/loop6 ro,seclabel
+";
+
+    const PM: &str = "\
+package:/data/app/~~Xq3VbJ8d0ZsR9vQmP2w==/com.google.android.youtube-5Lr0wPb8f4RgqFhtmYw==/base.apk=com.google.android.youtube
+package:/system/app/Chrome/Chrome.apk=com.android.chrome
+";
+
+    #[test]
+    fn finds_the_bind_mounted_apks() {
+        assert_eq!(
+            mounted_apps(MOUNTINFO, PM),
+            [
+                "com.google.android.youtube",
+                // not in the pm output, from the path
+                "com.google.android.apps.youtube.music",
+                "com.example.spaced",
+            ]
+        );
+        assert_eq!(mounted_apps(MOUNTINFO, "").len(), 3);
+        assert!(mounted_apps("", PM).is_empty());
+    }
+
+    #[test]
+    fn unescapes_the_mount_point() {
+        let line = MOUNTINFO.lines().nth(6).unwrap();
+        assert_eq!(
+            mount_point(line).unwrap(),
+            "/data/app/com.example.spaced-1/my app.apk"
+        );
+        assert_eq!(
+            // a truncated escape is kept as is
+            mount_point(r"1 2 3:4 / /a\134b\011c\d\04 - x y z").unwrap(),
+            "/a\\b\tc\\d\\04"
+        );
+        assert_eq!(mount_point("1 2 3:4 /"), None);
+    }
+
+    #[test]
+    fn package_from_the_apk_path() {
+        assert_eq!(
+            pkg_from_path("/data/app/~~abc==/com.app1-def==/base.apk").as_deref(),
+            Some("com.app1")
+        );
+        assert_eq!(
+            pkg_from_path("/data/app/org.xxx2-1/base.apk").as_deref(),
+            Some("org.xxx2")
+        );
+        assert_eq!(pkg_from_path("/data/app/base.apk"), None);
+    }
+}
@@ -0,0 +1,702 @@
+//! Port of the module's matcher, `detach()` and `is_target()` in zygisk/jni/module.cpp with
+//! `FakeParcel` of parcel.cpp. It is the executable spec of how detach.bin has to be laid out
+//! for the module to hide an app, keep it in step with the C++ side.
+
+use crate::pm_codes;
+
+pub const PM_DESCRIPTOR: &str = "android.content.pm.IPackageManager";
+
+const AID_USER_OFFSET: u32 = 100000;
+const TAG_USER_APP: u8 = 1;
+const TAG_STORE: u8 = 2;
+/// `kHeader` of `Parcel::writeInterfaceToken` since SDK 30, 'SYST' as an int32
+const SYST: u32 = u32::from_be_bytes(*b"SYST");
+
+/// Bytes before the interface token of a binder transaction, as the module picks them
+pub fn headers_len(sdk: u32) -> usize {
+    match sdk {
+        30.. => 3 * size_of::<u32>(),
+        29 => 2 * size_of::<u32>(),
+        _ => size_of::<u32>(),
+    }
+}
+
+/// The `(sdk, codes)` of the newest SDK of `pm_codes::CODES` not after `sdk`, like `pm_codes()`
+/// picks them.
+/// SDKs without an aidl/ file of their own (32, 35 and later) take the codes of the one
+/// before, SDKs older than the oldest file take its codes. Vendoring the file of such an SDK
+/// and re-running `detach aidl-gen` is the fix when its codes turn out different.
+fn pm_codes(sdk: u32) -> &'static (u32, [u32; pm_codes::METHODS.len()]) {
+    pm_codes::CODES
+        .iter()
+        .rev()
+        .find(|(s, _)| *s <= sdk)
+        .unwrap_or(&pm_codes::CODES[0])
+}
+
+/// The transaction code of `method` of `pm_codes::METHODS` on `sdk`
+pub fn pm_code(sdk: u32, method: &str) -> u32 {
+    let i = pm_codes::METHODS.iter().position(|m| *m == method).unwrap();
+    pm_codes(sdk).1[i]
+}
+
+/// The method of `pm_codes::METHODS` with the transaction code `code` on `sdk`
+pub fn pm_method(sdk: u32, code: u32) -> Option<&'static str> {
+    let i = pm_codes(sdk).1.iter().position(|c| *c == code)?;
+    Some(pm_codes::METHODS[i])
+}
+
+/// `FakeParcel`, reads past the end give `None` where the C++ side reads whatever is there
+struct FakeParcel<'a> {
+    data: &'a [u8],
+    cur: usize,
+}
+
+impl FakeParcel<'_> {
+    fn skip(&mut self, n: usize) {
+        self.cur += n;
+    }
+
+    fn read_int32(&mut self) -> Option<u32> {
+        let i = u32::from_le_bytes(self.data.get(self.cur..self.cur + 4)?.try_into().unwrap());
+        self.skip(4);
+        Some(i)
+    }
+
+    /// Offset of the chars of a string whose length was already read. Skips one int32
+    /// too many, which lines up because the null and padding take 4 bytes for the descriptor.
+    fn read_string16(&mut self, len: u32) -> usize {
+        let s = self.cur;
+        self.skip(4 + len as usize * 2);
+        s
+    }
+
+    fn enforce_interface(&mut self, headers: usize) -> Option<bool> {
+        if self.data.len() < min_len(headers) {
+            return Some(false);
+        }
+        self.skip(headers);
+        let len = self.read_int32()?;
+        self.read_string16(len);
+        Some(len as usize == PM_DESCRIPTOR.len())
+    }
+}
+
+/// The size check of `enforceInterface`: the token, a package name length and 4 more bytes
+fn min_len(headers: usize) -> usize {
+    headers + 4 + PM_DESCRIPTOR.len() * 2 + 4 * 2
+}
+
+/// Whether the module's `enforceInterface` takes `parcel` for an `IPackageManager`
+/// transaction of `sdk`, why not otherwise
+pub fn enforce_interface(parcel: &[u8], sdk: u32) -> Result<(), String> {
+    let headers = headers_len(sdk);
+    let mut p = FakeParcel {
+        data: parcel,
+        cur: 0,
+    };
+    if p.enforce_interface(headers) == Some(true) {
+        return Ok(());
+    }
+    if parcel.len() < min_len(headers) {
+        return Err(format!(
+            "rejected, {} bytes where it needs at least {}",
+            parcel.len(),
+            min_len(headers)
+        ));
+    }
+    let len = u32::from_le_bytes(parcel[headers..headers + 4].try_into().unwrap());
+    Err(format!(
+        "rejected, the token length at byte {headers} is {len}, not the {} of {PM_DESCRIPTOR}",
+        PM_DESCRIPTOR.len()
+    ))
+}
+
+pub enum Verdict {
+    /// the package name at `at` of the parcel is blanked, it matches the record at `offset`
+    /// of detach.bin, detached for `user` or every user
+    Zeroed {
+        at: usize,
+        offset: usize,
+        user: Option<u32>,
+    },
+    /// too short or not an `IPackageManager` interface token at the expected offset
+    NotPackageManager,
+    /// no record can be that long
+    TooLong,
+    GetPackageInfo,
+    NotDetached,
+}
+
+impl std::fmt::Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Zeroed { at, offset, user } => {
+                write!(
+                    f,
+                    "zeroed at byte {at} of the parcel, it matches the record at byte {offset} of detach.bin"
+                )?;
+                match user {
+                    Some(user) => write!(f, " detached for user {user}"),
+                    None => write!(f, " detached for every user"),
+                }
+            }
+            Self::NotPackageManager => write!(
+                f,
+                "left alone, enforceInterface does not find IPackageManager after the headers"
+            ),
+            Self::TooLong => write!(f, "left alone, the name is too long for detach.bin"),
+            Self::GetPackageInfo => write!(f, "left alone, getPackageInfo is never touched"),
+            Self::NotDetached => write!(f, "left alone, no record of detach.bin matches it"),
+        }
+    }
+}
+
+/// The module as loaded in a store process
+pub struct Module {
+    /// detach.bin with the 0 `read_companion` appends
+    detach_txt: Vec<u8>,
+    headers_len: usize,
+    /// `getPackageInfo_code`, transactions with it are never touched
+    get_package_info: u32,
+    user_id: u32,
+}
+
+impl Module {
+    pub fn new(detach_bin: &[u8], sdk: u32, uid: u32) -> Self {
+        let mut detach_txt = detach_bin.to_vec();
+        detach_txt.push(0);
+        Self {
+            detach_txt,
+            headers_len: headers_len(sdk),
+            get_package_info: pm_code(sdk, "getPackageInfo"),
+            user_id: uid / AID_USER_OFFSET,
+        }
+    }
+
+    /// `(offset, record)` until the first 0 length
+    fn records(&self) -> impl Iterator<Item = (usize, &[u8])> {
+        let mut i = 0;
+        std::iter::from_fn(move || {
+            let offset = i;
+            let dlen = *self.detach_txt.get(i).filter(|&&l| l != 0)? as usize;
+            let record = self.detach_txt.get(i + 1..i + 1 + dlen)?;
+            i += 1 + dlen;
+            Some((offset, record))
+        })
+    }
+
+    #[cfg(test)]
+    /// `transact_hook`, blanks the first char of the package name of `parcel` if it is
+    /// detached for this user. Returns whether it did.
+    pub fn detach(&self, parcel: &mut [u8], code: u32) -> bool {
+        let Verdict::Zeroed { at, .. } = self.check(parcel, code) else {
+            return false;
+        };
+        parcel[at] = 0;
+        parcel[at + 1] = 0;
+        true
+    }
+
+    /// What `detach` does to `parcel` and why
+    pub fn check(&self, parcel: &[u8], code: u32) -> Verdict {
+        let mut p = FakeParcel {
+            data: parcel,
+            cur: 0,
+        };
+        if p.enforce_interface(self.headers_len) != Some(true) {
+            return Verdict::NotPackageManager;
+        }
+        let Some(pkg_len) = p.read_int32() else {
+            return Verdict::NotPackageManager;
+        };
+        let pkg_len_b = (pkg_len as usize * 2).wrapping_sub(1);
+        if pkg_len_b > u8::MAX as usize {
+            return Verdict::TooLong;
+        }
+        if code == self.get_package_info {
+            return Verdict::GetPackageInfo;
+        }
+        let at = p.read_string16(pkg_len);
+        let Some(pkg_bytes) = parcel.get(at..at + pkg_len_b) else {
+            return Verdict::NotPackageManager;
+        };
+        for (offset, mut record) in self.records() {
+            let mut user = None;
+            // even length: tagged record, pkg_len_b is always odd
+            if record.len() % 2 == 0 {
+                if record[0] != TAG_USER_APP || record.len() <= 1 + 4 {
+                    continue;
+                }
+                let id = u32::from_le_bytes(record[1..5].try_into().unwrap());
+                if id != self.user_id {
+                    continue;
+                }
+                user = Some(id);
+                record = &record[5..];
+            }
+            if record == pkg_bytes {
+                return Verdict::Zeroed { at, offset, user };
+            }
+        }
+        Verdict::NotDetached
+    }
+
+    /// `is_target`, whether the module stays loaded in `store`
+    pub fn is_target(&self, store: &str) -> bool {
+        let mut any = false;
+        for (_, record) in self.records() {
+            if record.len() % 2 == 1 || record[0] != TAG_STORE {
+                continue;
+            }
+            any = true;
+            if record[1..] == encode(store) {
+                return true;
+            }
+        }
+        !any && store == crate::stores::STORES[0].package
+    }
+}
+
+/// An ASCII name as detach.bin stores it, UTF-16LE minus the last 0
+fn encode(name: &str) -> Vec<u8> {
+    let mut utf16: Vec<u8> = name.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    utf16.pop();
+    utf16
+}
+
+/// `Parcel::writeString16`: length, UTF-16 chars, a null, padded to 4 bytes
+fn write_string16(p: &mut Vec<u8>, s: &str) {
+    let chars: Vec<u16> = s.encode_utf16().collect();
+    p.extend_from_slice(&(chars.len() as u32).to_le_bytes());
+    for c in chars {
+        p.extend_from_slice(&c.to_le_bytes());
+    }
+    p.extend_from_slice(&[0, 0]);
+    while !p.len().is_multiple_of(4) {
+        p.push(0);
+    }
+}
+
+/// A transaction to `descriptor` with the package name as its first argument, laid out
+/// like `Parcel::writeInterfaceToken` of `sdk` writes it, followed by flags and a user id
+pub fn synthesize(sdk: u32, descriptor: &str, pkg: &str, user: u32) -> Vec<u8> {
+    let mut p = Vec::new();
+    // StrictMode policy
+    p.extend_from_slice(&0x0000_0100u32.to_le_bytes());
+    if sdk >= 29 {
+        // work source uid, -1 for unset
+        p.extend_from_slice(&u32::MAX.to_le_bytes());
+    }
+    if sdk >= 30 {
+        p.extend_from_slice(&SYST.to_le_bytes());
+    }
+    write_string16(&mut p, descriptor);
+    write_string16(&mut p, pkg);
+    p.extend_from_slice(&0u64.to_le_bytes());
+    p.extend_from_slice(&user.to_le_bytes());
+    p
+}
+
+/// The bytes of a dump, `xxd`/`detach bugreport` style hex lines or plain hex are decoded,
+/// anything else is taken as the raw parcel
+pub fn parse_dump(dump: &[u8]) -> Result<Vec<u8>, String> {
+    if dump.is_empty() {
+        return Err("the dump is empty".to_string());
+    }
+    if !dump
+        .iter()
+        .all(|b| b.is_ascii_graphic() || b.is_ascii_whitespace())
+    {
+        return Ok(dump.to_vec());
+    }
+    let mut digits = String::new();
+    for line in String::from_utf8_lossy(dump).lines() {
+        let mut line = line.trim();
+        // 00000000: 0100 0000 ffff ffff  ........
+        if let Some((offset, rest)) = line.split_once(':')
+            && !offset.is_empty()
+            && offset.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            line = rest.trim_start();
+            line = line.split_once("  ").map_or(line, |(hex, _ascii)| hex);
+        }
+        for token in line.split(|c: char| c.is_ascii_whitespace() || c == ',') {
+            let token = token.strip_prefix("0x").unwrap_or(token);
+            if !token.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!("not hex: '{token}'"));
+            }
+            digits.push_str(token);
+        }
+    }
+    if digits.len() % 2 == 1 {
+        return Err("odd number of hex digits".to_string());
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|e| e.to_string()))
+        .collect()
+}
+
+/// A field read off a parcel
+pub struct Field {
+    pub offset: usize,
+    pub name: &'static str,
+    pub value: String,
+}
+
+impl std::fmt::Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:04x}  {:<20}{}", self.offset, self.name, self.value)
+    }
+}
+
+fn int32_at(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(at..at + 4)?.try_into().unwrap(),
+    ))
+}
+
+/// The chars of a string16 at `at` and the offset past its padding, `None` if it does not
+/// look like one
+fn string16_at(data: &[u8], at: usize) -> Option<(String, usize)> {
+    let len = int32_at(data, at)? as usize;
+    // an empty string is more likely a 0 int32
+    if len == 0 || len > data.len() {
+        return None;
+    }
+    let end = (at + 4 + len * 2 + 2).next_multiple_of(4);
+    let units: Vec<u16> = data
+        .get(at + 4..end)?
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    if units[len] != 0 {
+        return None;
+    }
+    let s: String = char::decode_utf16(units[..len].iter().copied())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    (!s.chars().any(char::is_control)).then_some((s, end))
+}
+
+fn int32_value(i: u32) -> String {
+    format!("{} (0x{i:08x})", i as i32)
+}
+
+/// The headers length of the SDK `parcel` was written by, from where its interface token is
+pub fn guess_headers_len(parcel: &[u8]) -> Option<usize> {
+    [28, 29, 30]
+        .map(headers_len)
+        .into_iter()
+        .rev()
+        .find(|&h| string16_at(parcel, h).is_some())
+}
+
+/// The headers `Parcel::writeInterfaceToken` writes for `headers_len`, the interface token,
+/// then the rest read as string16s where they look like one and int32s otherwise
+pub fn decode(parcel: &[u8], headers_len: usize) -> Vec<Field> {
+    let mut fields = Vec::new();
+    let names = ["strict mode policy", "work source uid", "vendor header"];
+    let mut at = 0;
+    for (n, &name) in names[..headers_len / 4].iter().enumerate() {
+        let Some(i) = int32_at(parcel, at) else {
+            break;
+        };
+        let value = match (n, i) {
+            (0, _) => format!("0x{i:08x}"),
+            (1, u32::MAX) => "-1 (unset)".to_string(),
+            (1, _) => i.to_string(),
+            (_, SYST) => "'SYST'".to_string(),
+            _ => format!("0x{i:08x}, not 'SYST'"),
+        };
+        fields.push(Field {
+            offset: at,
+            name,
+            value,
+        });
+        at += 4;
+    }
+    let mut name = "interface token";
+    while at < parcel.len() {
+        if let Some((s, end)) = string16_at(parcel, at) {
+            fields.push(Field {
+                offset: at,
+                name,
+                value: format!("\"{s}\" ({} chars)", s.encode_utf16().count()),
+            });
+            at = end;
+        } else if let Some(i) = int32_at(parcel, at) {
+            fields.push(Field {
+                offset: at,
+                name: if name == "interface token" {
+                    "no interface token"
+                } else {
+                    "int32"
+                },
+                value: int32_value(i),
+            });
+            at += 4;
+        } else {
+            fields.push(Field {
+                offset: at,
+                name: "trailing bytes",
+                value: format!("{:02x?}", &parcel[at..]),
+            });
+            break;
+        }
+        name = "string16";
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const SDKS: std::ops::RangeInclusive<u32> = 28..=35;
+    /// an arbitrary app uid of user 0 and of user 10
+    const UID_0: u32 = 10123;
+    const UID_10: u32 = 10 * AID_USER_OFFSET + 10123;
+    const GET_APPLICATION_INFO: u32 = 4;
+
+    /// detach.bin as `bin_serialize` writes it
+    fn serialize(apps: &[(&str, Option<u32>)]) -> Vec<u8> {
+        static N: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "zygisk-detach-parcel-{}-{}",
+            std::process::id(),
+            N.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut f = File::create(&path).unwrap();
+        for (app, user) in apps {
+            crate::bin_serialize(app, *user, &mut f).unwrap();
+        }
+        let bin = fs::read(&path).unwrap();
+        fs::remove_file(path).unwrap();
+        bin
+    }
+
+    fn hides(module: &Module, sdk: u32, pkg: &str, code: u32) -> bool {
+        let mut parcel = synthesize(sdk, PM_DESCRIPTOR, pkg, 0);
+        let before = parcel.clone();
+        let hit = module.detach(&mut parcel, code);
+        let changed: Vec<usize> = (0..parcel.len())
+            .filter(|&i| parcel[i] != before[i])
+            .collect();
+        if hit {
+            // only the first char of the name
+            let at = headers_len(sdk) + 4 + 72 + 4;
+            assert!(
+                changed.iter().all(|&i| i == at || i == at + 1),
+                "{changed:?}"
+            );
+        } else {
+            assert!(changed.is_empty());
+        }
+        hit
+    }
+
+    /// Names around the detached ones that must be left alone
+    const NEAR_MISSES: [&str; 8] = [
+        "com.app",
+        "com.app1x",
+        "com.app2",
+        "xom.app1",
+        "com.APP1",
+        "org.xxx",
+        "org.xxx22",
+        "com.android.vending",
+    ];
+
+    #[test]
+    fn hides_exactly_the_detached_apps() {
+        let detached = ["com.app1", "org.xxx2", "a", &"x".repeat(128)];
+        let apps: Vec<(&str, Option<u32>)> = detached.iter().map(|a| (*a, None)).collect();
+        let bin = serialize(&apps);
+        for sdk in SDKS {
+            let module = Module::new(&bin, sdk, UID_0);
+            for app in detached {
+                assert!(
+                    hides(&module, sdk, app, GET_APPLICATION_INFO),
+                    "{app} sdk {sdk}"
+                );
+            }
+            for app in NEAR_MISSES {
+                assert!(
+                    !hides(&module, sdk, app, GET_APPLICATION_INFO),
+                    "{app} sdk {sdk}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn user_records_only_hide_for_their_user() {
+        let bin = serialize(&[("com.app1", Some(10)), ("org.xxx2", None)]);
+        for sdk in SDKS {
+            let owner = Module::new(&bin, sdk, UID_10);
+            let other = Module::new(&bin, sdk, UID_0);
+            assert!(hides(&owner, sdk, "com.app1", GET_APPLICATION_INFO));
+            assert!(!hides(&other, sdk, "com.app1", GET_APPLICATION_INFO));
+            assert!(hides(&owner, sdk, "org.xxx2", GET_APPLICATION_INFO));
+            assert!(hides(&other, sdk, "org.xxx2", GET_APPLICATION_INFO));
+            for app in NEAR_MISSES {
+                assert!(!hides(&owner, sdk, app, GET_APPLICATION_INFO));
+            }
+        }
+    }
+
+    #[test]
+    fn pm_codes_fall_back_to_the_sdk_before() {
+        let picked = |sdk| pm_codes(sdk).0;
+        let (oldest, _) = pm_codes::CODES[0];
+        let (newest, _) = pm_codes::CODES[pm_codes::CODES.len() - 1];
+        assert_eq!(picked(oldest - 2), oldest);
+        for (sdk, _) in pm_codes::CODES {
+            assert_eq!(picked(sdk), sdk);
+        }
+        // no aidl/ file of 32 or 35
+        assert_eq!(picked(32), 31);
+        assert_eq!(picked(newest + 1), newest);
+
+        for sdk in [oldest - 1, 32, newest + 1] {
+            let code = pm_code(sdk, "getPackageInfo");
+            assert_eq!(pm_method(sdk, code), Some("getPackageInfo"));
+        }
+        assert_eq!(pm_code(33, "getPackageUid"), 5);
+        assert_eq!(pm_method(33, 1), None);
+    }
+
+    #[test]
+    fn store_records_are_not_apps() {
+        let mut bin = serialize(&[("com.app1", None)]);
+        bin.extend(crate::bin_record("com.sec.android.app.samsungapps", &[TAG_STORE]).unwrap());
+        for sdk in SDKS {
+            let module = Module::new(&bin, sdk, UID_0);
+            assert!(hides(&module, sdk, "com.app1", GET_APPLICATION_INFO));
+            assert!(!hides(
+                &module,
+                sdk,
+                "com.sec.android.app.samsungapps",
+                GET_APPLICATION_INFO
+            ));
+        }
+    }
+
+    #[test]
+    fn leaves_get_package_info_alone() {
+        let bin = serialize(&[("com.app1", None)]);
+        for sdk in SDKS {
+            let module = Module::new(&bin, sdk, UID_0);
+            let get_package_info = pm_code(sdk, "getPackageInfo");
+            assert!(!hides(&module, sdk, "com.app1", get_package_info));
+        }
+    }
+
+    #[test]
+    fn needs_the_package_manager_and_the_right_headers() {
+        let bin = serialize(&[("com.app1", None)]);
+        for sdk in SDKS {
+            let module = Module::new(&bin, sdk, UID_0);
+            let mut other = synthesize(sdk, "android.content.pm.IPackageInstaller", "com.app1", 0);
+            assert!(!module.detach(&mut other, GET_APPLICATION_INFO));
+            // a parcel of another SDK's layout is read at the wrong offset
+            for parcel_sdk in [28, 29, 30] {
+                if headers_len(parcel_sdk) != headers_len(sdk) {
+                    let mut p = synthesize(parcel_sdk, PM_DESCRIPTOR, "com.app1", 0);
+                    assert!(!module.detach(&mut p, GET_APPLICATION_INFO));
+                }
+            }
+            let mut short = synthesize(sdk, PM_DESCRIPTOR, "com.app1", 0);
+            short.truncate(headers_len(sdk) + 4 + 72);
+            assert!(!module.detach(&mut short, GET_APPLICATION_INFO));
+        }
+    }
+
+    #[test]
+    fn stops_at_an_empty_record() {
+        let mut bin = serialize(&[("com.app1", None)]);
+        bin.push(0);
+        bin.extend(serialize(&[("org.xxx2", None)]));
+        let module = Module::new(&bin, 34, UID_0);
+        assert!(hides(&module, 34, "com.app1", GET_APPLICATION_INFO));
+        assert!(!hides(&module, 34, "org.xxx2", GET_APPLICATION_INFO));
+    }
+
+    #[test]
+    fn targets_the_stores_of_the_store_records() {
+        let bin = serialize(&[("com.app1", None)]);
+        let module = Module::new(&bin, 34, UID_0);
+        assert!(module.is_target("com.android.vending"));
+        assert!(!module.is_target("com.huawei.appmarket"));
+
+        let mut bin = bin;
+        bin.extend(crate::bin_record("com.huawei.appmarket", &[TAG_STORE]).unwrap());
+        let module = Module::new(&bin, 34, UID_0);
+        assert!(!module.is_target("com.android.vending"));
+        assert!(module.is_target("com.huawei.appmarket"));
+        assert!(!module.is_target("com.huawei.appmarke"));
+    }
+
+    #[test]
+    fn parses_hex_dumps() {
+        let parcel = synthesize(34, PM_DESCRIPTOR, "com.app1", 0);
+        assert_eq!(parse_dump(&parcel).unwrap(), parcel);
+        let plain: String = parcel.iter().map(|b| format!("{b:02x} ")).collect();
+        assert_eq!(parse_dump(plain.as_bytes()).unwrap(), parcel);
+        let xxd = "00000000: 0001 0000 ffff ffff 5453 5953 2200 0000  ........TSYS\"...\n\
+                   00000010: 6100 6e00                                a.n.\n";
+        assert_eq!(
+            parse_dump(xxd.as_bytes()).unwrap(),
+            [&parcel[..16], &[b'a', 0, b'n', 0]].concat()
+        );
+        assert!(parse_dump(b"01 02 zz").is_err());
+        assert!(parse_dump(b"012").is_err());
+    }
+
+    #[test]
+    fn decodes_the_headers_and_fields() {
+        for sdk in SDKS {
+            let parcel = synthesize(sdk, PM_DESCRIPTOR, "com.app1", 10);
+            let headers = headers_len(sdk);
+            assert_eq!(guess_headers_len(&parcel), Some(headers));
+            let fields: Vec<(usize, &str, String)> = decode(&parcel, headers)
+                .into_iter()
+                .map(|f| (f.offset, f.name, f.value))
+                .collect();
+            let token = headers + 4 + 72;
+            let mut expected = vec![
+                (0, "strict mode policy", "0x00000100".to_string()),
+                (4, "work source uid", "-1 (unset)".to_string()),
+                (8, "vendor header", "'SYST'".to_string()),
+            ];
+            expected.truncate(headers / 4);
+            expected.extend([
+                (
+                    headers,
+                    "interface token",
+                    format!("\"{PM_DESCRIPTOR}\" (34 chars)"),
+                ),
+                (token, "string16", "\"com.app1\" (8 chars)".to_string()),
+                (token + 24, "int32", "0 (0x00000000)".to_string()),
+                (token + 28, "int32", "0 (0x00000000)".to_string()),
+                (token + 32, "int32", "10 (0x0000000a)".to_string()),
+            ]);
+            assert_eq!(fields, expected, "sdk {sdk}");
+            assert_eq!(enforce_interface(&parcel, sdk), Ok(()));
+            for other in [28, 29, 30] {
+                if headers_len(other) != headers {
+                    assert!(enforce_interface(&parcel, other).is_err());
+                }
+            }
+        }
+        let short = synthesize(34, PM_DESCRIPTOR, "a", 0);
+        assert_eq!(
+            enforce_interface(&short[..90], 34),
+            Err("rejected, 90 bytes where it needs at least 92".to_string())
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// The cache file of `user` next to `base`, every user has its own
+pub fn path(base: &str, user: Option<u32>) -> String {
+    match user {
+        Some(user) => format!("{base}.user{user}"),
+        None => format!("{base}.current"),
+    }
+}
+
+/// Cache key from the modification times of the package manager's state files.
+/// Any install, update or uninstall touches them and invalidates the cache.
+/// `None` if one of them cannot be stat'ed, the cache could not tell when it is stale.
+pub fn key(sources: &[&str]) -> Option<String> {
+    let mut key = String::new();
+    for src in sources {
+        let mtime = fs::metadata(src)
+            .and_then(|m| m.modified())
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        key.push_str(&format!("{}.{} ", mtime.as_secs(), mtime.subsec_nanos()));
+    }
+    Some(key)
+}
+
+/// The cached `pm list packages` output if the cache was stored with `key`
+pub fn load(path: &str, key: &str) -> Option<Vec<u8>> {
+    let content = fs::read(path).ok()?;
+    let nl = content.iter().position(|&b| b == b'\n')?;
+    if &content[..nl] != key.as_bytes() {
+        return None;
+    }
+    Some(content[nl + 1..].to_vec())
+}
+
+pub fn store(path: &str, key: &str, pkgs: &[u8]) -> io::Result<()> {
+    let mut content = Vec::with_capacity(key.len() + 1 + pkgs.len());
+    content.extend_from_slice(key.as_bytes());
+    content.push(b'\n');
+    content.extend_from_slice(pkgs);
+    fs::write(path, content)
+}
+
+/// Deletes the cache files of every user, returns how many there were
+pub fn clear(base: &str) -> io::Result<usize> {
+    let base = Path::new(base);
+    let dir = match base.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = base.file_name().unwrap_or_default().to_string_lossy();
+    let prefix = format!("{name}.");
+    let mut n = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        // the single cache file of older versions too
+        if file_name == name || file_name.starts_with(&prefix) {
+            fs::remove_file(entry.path())?;
+            n += 1;
+        }
+    }
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::time::{Duration, SystemTime};
+
+    fn temp_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!(
+            "zygisk-detach-pkg-cache-{name}-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn key_follows_the_sources() {
+        let dir = temp_dir("key");
+        let (list, xml) = (
+            format!("{dir}/packages.list"),
+            format!("{dir}/packages.xml"),
+        );
+        let sources = [list.as_str(), xml.as_str()];
+        fs::write(&list, "").unwrap();
+        // a missing source never gives a key
+        assert_eq!(key(&sources), None);
+        fs::write(&xml, "").unwrap();
+        let before = key(&sources).unwrap();
+        assert_eq!(key(&sources).unwrap(), before);
+
+        let later = SystemTime::now() + Duration::from_secs(60);
+        File::options()
+            .write(true)
+            .open(&xml)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_ne!(key(&sources).unwrap(), before);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stores_one_entry_per_user() {
+        let dir = temp_dir("store");
+        let base = format!("{dir}/packages.cache");
+        let (current, user10) = (path(&base, None), path(&base, Some(10)));
+        assert_ne!(current, user10);
+        assert_eq!(load(&current, "k1"), None);
+
+        store(&current, "k1", b"package:a=com.app1\n").unwrap();
+        store(&user10, "k1", b"package:b=org.xxx2\n").unwrap();
+        assert_eq!(load(&current, "k1").unwrap(), b"package:a=com.app1\n");
+        assert_eq!(load(&user10, "k1").unwrap(), b"package:b=org.xxx2\n");
+        // stale
+        assert_eq!(load(&current, "k2"), None);
+
+        fs::write(format!("{dir}/detach.bin"), "").unwrap();
+        fs::write(&base, "old").unwrap();
+        assert_eq!(clear(&base).unwrap(), 3);
+        assert_eq!(load(&user10, "k1"), None);
+        assert_eq!(clear(&base).unwrap(), 0);
+        assert!(Path::new(&format!("{dir}/detach.bin")).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
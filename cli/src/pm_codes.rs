@@ -0,0 +1,18 @@
+//! Generated by `detach aidl-gen` from aidl/, do not edit
+
+pub const METHODS: [&str; 4] = [
+    "isPackageAvailable",
+    "getPackageInfo",
+    "getPackageUid",
+    "getPackageGids",
+];
+
+/// `(sdk, codes of METHODS)`, by SDK
+pub const CODES: [(u32, [u32; 4]); 6] = [
+    (28, [2, 3, 5, 6]),
+    (29, [2, 3, 5, 6]),
+    (30, [2, 3, 5, 6]),
+    (31, [2, 3, 5, 6]),
+    (33, [2, 3, 5, 6]),
+    (34, [2, 3, 5, 6]),
+];
@@ -0,0 +1,296 @@
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::stores::Store;
+
+const PROC: &str = "/proc";
+const AID_USER_OFFSET: u32 = 100000;
+const SIGKILL: i32 = 9;
+/// How long each of `am force-stop` and SIGKILL gets to take the processes down
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+unsafe extern "C" {
+    fn kill(pid: i32, sig: i32) -> i32;
+}
+
+#[derive(Clone)]
+pub struct Process {
+    pub pid: i32,
+    pub uid: u32,
+    /// arguments joined with spaces
+    pub cmdline: String,
+}
+
+impl Display for Process {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (uid {}) {}", self.pid, self.uid, self.cmdline)
+    }
+}
+
+impl Process {
+    /// Gone, or the pid was reused by another process
+    fn is_gone(&self) -> bool {
+        read_cmdline(PROC, self.pid).is_none_or(|cmdline| cmdline != self.cmdline)
+    }
+}
+
+fn read_cmdline(proc: &str, pid: i32) -> Option<String> {
+    let raw = fs::read(format!("{proc}/{pid}/cmdline")).ok()?;
+    let args: Vec<String> = raw
+        .split(|&b| b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect();
+    Some(args.join(" "))
+}
+
+fn read_uid(proc: &str, pid: i32) -> Option<u32> {
+    let status = fs::read_to_string(format!("{proc}/{pid}/status")).ok()?;
+    let uids = status.lines().find_map(|l| l.strip_prefix("Uid:"))?;
+    uids.split_whitespace().next()?.parse().ok()
+}
+
+/// Running processes named one of `names` or one of their `:sub` processes,
+/// of `user` or of every user if `None`
+pub fn find(names: &[&str], user: Option<u32>) -> io::Result<Vec<Process>> {
+    find_in(PROC, names, user)
+}
+
+fn find_in(proc: &str, names: &[&str], user: Option<u32>) -> io::Result<Vec<Process>> {
+    let mut found = Vec::new();
+    for entry in fs::read_dir(proc)? {
+        let Ok(pid) = entry?.file_name().to_string_lossy().parse::<i32>() else {
+            continue;
+        };
+        // kernel threads have no cmdline, processes may exit while we look
+        let Some(cmdline) = read_cmdline(proc, pid) else {
+            continue;
+        };
+        let name = cmdline.split(' ').next().unwrap_or_default();
+        let matches = names.iter().any(|n| {
+            name.strip_prefix(n)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+        });
+        if !matches {
+            continue;
+        }
+        let Some(uid) = read_uid(proc, pid) else {
+            continue;
+        };
+        if user.is_none_or(|user| uid / AID_USER_OFFSET == user) {
+            found.push(Process { pid, uid, cmdline });
+        }
+    }
+    Ok(found)
+}
+
+/// `am force-stop` of `package`, `false` if it could not be run or failed
+fn force_stop(package: &str, user: Option<u32>) -> bool {
+    let user = user.map_or("all".to_string(), |u| u.to_string());
+    Command::new("am")
+        .args(["force-stop", "--user", &user, package])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+fn sigkill(pid: i32) -> io::Result<()> {
+    if unsafe { kill(pid, SIGKILL) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Waits up to `TIMEOUT` for `procs` to exit, returns the ones still running
+fn wait_gone(mut procs: Vec<Process>) -> Vec<Process> {
+    let start = Instant::now();
+    loop {
+        procs.retain(|p| !p.is_gone());
+        if procs.is_empty() || start.elapsed() >= TIMEOUT {
+            return procs;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// What `stop` did to a store
+pub struct Stop {
+    pub store: &'static Store,
+    /// the processes running before
+    pub found: Vec<Process>,
+    pub force_stopped: bool,
+    /// processes that had to be SIGKILLed
+    pub killed: usize,
+    pub remaining: Vec<Process>,
+}
+
+impl Stop {
+    /// The store reads detach.bin again when it starts
+    pub fn applied(&self) -> bool {
+        self.remaining.is_empty()
+    }
+}
+
+impl Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let label = self.store.label;
+        if self.found.is_empty() {
+            write!(f, "{label}: not running")?;
+        } else if !self.remaining.is_empty() {
+            write!(
+                f,
+                "{label}: {} of {} processes still running, reboot to apply the changes",
+                self.remaining.len(),
+                self.found.len()
+            )?;
+        } else {
+            let how = match (self.force_stopped, self.killed) {
+                (true, 0) => "am force-stop".to_string(),
+                (true, n) => format!("am force-stop and SIGKILL for {n}"),
+                (false, _) => "SIGKILL".to_string(),
+            };
+            let n = self.found.len();
+            let s = if n == 1 { "" } else { "es" };
+            write!(f, "{label}: stopped {n} process{s} with {how}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Stops the processes of `store` of `user`, or of every user if `None`, with `am force-stop`
+/// and SIGKILLs what survives it. Checks that they are gone, the system starts the store
+/// again the next time something needs it.
+pub fn stop(store: &'static Store, user: Option<u32>) -> io::Result<Stop> {
+    let found = find(store.processes, user)?;
+    let mut stop = Stop {
+        store,
+        found: Vec::new(),
+        force_stopped: false,
+        killed: 0,
+        remaining: Vec::new(),
+    };
+    if !found.is_empty() {
+        let mut remaining: Vec<Process> = found.clone();
+        stop.force_stopped = force_stop(store.package, user);
+        if stop.force_stopped {
+            remaining = wait_gone(remaining);
+        }
+        if !remaining.is_empty() {
+            for p in &remaining {
+                // ESRCH, it exited meanwhile
+                if sigkill(p.pid).is_ok() {
+                    stop.killed += 1;
+                }
+            }
+            remaining = wait_gone(remaining);
+        }
+        stop.found = found;
+        stop.remaining = remaining;
+    }
+    Ok(stop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::STORES;
+
+    fn process(pid: i32, uid: u32) -> Process {
+        Process {
+            pid,
+            uid,
+            cmdline: "com.android.vending".to_string(),
+        }
+    }
+
+    fn stop(found: Vec<Process>, force_stopped: bool, killed: usize) -> Stop {
+        Stop {
+            store: &STORES[0],
+            found,
+            force_stopped,
+            killed,
+            remaining: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn shows_what_stop_did() {
+        assert_eq!(
+            stop(vec![], false, 0).to_string(),
+            "Google Play Store: not running"
+        );
+        let one = stop(vec![process(1, 10_042)], true, 0);
+        assert!(one.applied());
+        assert_eq!(
+            one.to_string(),
+            "Google Play Store: stopped 1 process with am force-stop"
+        );
+        let two = vec![process(1, 10_042), process(2, 1_010_042)];
+        assert_eq!(
+            stop(two.clone(), true, 1).to_string(),
+            "Google Play Store: stopped 2 processes with am force-stop and SIGKILL for 1"
+        );
+        assert_eq!(
+            stop(two.clone(), false, 2).to_string(),
+            "Google Play Store: stopped 2 processes with SIGKILL"
+        );
+        let mut stuck = stop(two, false, 0);
+        stuck.remaining = vec![process(2, 1_010_042)];
+        assert!(!stuck.applied());
+        assert_eq!(
+            stuck.to_string(),
+            "Google Play Store: 1 of 2 processes still running, reboot to apply the changes"
+        );
+        assert_eq!(
+            process(1, 10_042).to_string(),
+            "1 (uid 10042) com.android.vending"
+        );
+    }
+
+    #[test]
+    fn finds_processes_by_name_and_user() {
+        let proc = std::env::temp_dir().join(format!("zygisk-detach-proc-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&proc);
+        for (pid, cmdline, uid) in [
+            ("100", &b"com.android.vending\0"[..], 10_042),
+            ("101", b"com.android.vending:background\0--flag\0", 10_042),
+            ("102", b"com.android.vending\0", 1_010_042),
+            // another app sharing the prefix
+            ("103", b"com.android.vendingx\0", 10_050),
+            ("104", b"", 0),
+        ] {
+            let dir = proc.join(pid);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("cmdline"), cmdline).unwrap();
+            let status = format!("Name:\tx\nUid:\t{uid}\t{uid}\t{uid}\t{uid}\n");
+            fs::write(dir.join("status"), status).unwrap();
+        }
+        fs::create_dir_all(proc.join("self")).unwrap();
+        let proc_dir = proc.to_string_lossy();
+        let pids = |user| {
+            let mut pids: Vec<i32> = find_in(&proc_dir, &["com.android.vending"], user)
+                .unwrap()
+                .iter()
+                .map(|p| p.pid)
+                .collect();
+            pids.sort();
+            pids
+        };
+        assert_eq!(pids(None), [100, 101, 102]);
+        assert_eq!(pids(Some(0)), [100, 101]);
+        assert_eq!(pids(Some(10)), [102]);
+        assert_eq!(pids(Some(11)), []);
+
+        let found = find_in(&proc_dir, &["com.android.vending"], Some(10)).unwrap();
+        assert_eq!(found[0].uid, 1_010_042);
+        let sub = find_in(&proc_dir, &["com.android.vending:background"], None).unwrap();
+        assert_eq!(sub[0].cmdline, "com.android.vending:background --flag");
+        fs::remove_dir_all(proc).unwrap();
+    }
+}
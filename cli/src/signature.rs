@@ -0,0 +1,222 @@
+use std::fmt::Display;
+use std::io;
+use std::path::Path;
+
+use crate::apk;
+
+pub type Digest = [u8; 32];
+
+/// Known upstream signing certificate digests, one `<package> <sha256>` per line.
+/// The digest can be plain or colon separated hex (apksigner / keytool output).
+pub struct AllowList(Vec<(String, Digest)>);
+
+impl AllowList {
+    pub fn parse(txt: &str) -> Result<Self, String> {
+        let mut entries = Vec::new();
+        for (n, line) in txt.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((pkg, digest)) = line.split_once(char::is_whitespace) else {
+                return Err(format!("line {}: expected '<package> <sha256>'", n + 1));
+            };
+            let Some(digest) = parse_hex(digest.trim()) else {
+                return Err(format!("line {}: invalid sha256 digest", n + 1));
+            };
+            entries.push((pkg.to_string(), digest));
+        }
+        Ok(Self(entries))
+    }
+
+    pub fn packages(&self) -> impl Iterator<Item = &str> {
+        let mut seen: Vec<&str> = Vec::new();
+        self.0.iter().filter_map(move |(pkg, _)| {
+            if seen.contains(&pkg.as_str()) {
+                None
+            } else {
+                seen.push(pkg);
+                Some(pkg.as_str())
+            }
+        })
+    }
+
+    fn digests(&self, pkg: &str) -> impl Iterator<Item = &Digest> {
+        self.0.iter().filter(move |(p, _)| p == pkg).map(|(_, d)| d)
+    }
+}
+
+pub enum SigStatus {
+    /// signed by one of the allowed certificates
+    Match,
+    /// re-signed with the certificate of this digest, the app is a detach candidate
+    Mismatch(Digest),
+    /// no known upstream digest for the package
+    Unlisted,
+    Unreadable(String),
+}
+
+pub struct SigCheck {
+    pub pkg: String,
+    pub status: SigStatus,
+}
+
+impl SigCheck {
+    pub fn is_candidate(&self) -> bool {
+        matches!(self.status, SigStatus::Mismatch(_))
+    }
+}
+
+impl Display for SigCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.status {
+            SigStatus::Match => write!(f, "{}: signature matches", self.pkg),
+            SigStatus::Mismatch(digest) => write!(
+                f,
+                "{}: signature {} is not in the allow-list",
+                self.pkg,
+                hex(digest)
+            ),
+            SigStatus::Unlisted => write!(f, "{}: no known upstream signature", self.pkg),
+            SigStatus::Unreadable(e) => write!(f, "{}: could not read signature: {e}", self.pkg),
+        }
+    }
+}
+
+pub fn inspect(pkg: &str, apk_path: &Path, allow: &AllowList) -> SigCheck {
+    check(pkg, apk::signer_cert_digest(apk_path), allow)
+}
+
+/// `digest` of `pkg` against the allow-list
+fn check(pkg: &str, digest: io::Result<Digest>, allow: &AllowList) -> SigCheck {
+    let digest = match digest {
+        Ok(d) => d,
+        Err(e) => {
+            return SigCheck {
+                pkg: pkg.to_string(),
+                status: SigStatus::Unreadable(e.to_string()),
+            };
+        }
+    };
+    let mut allowed = allow.digests(pkg).peekable();
+    let status = if allowed.peek().is_none() {
+        SigStatus::Unlisted
+    } else if allowed.any(|d| *d == digest) {
+        SigStatus::Match
+    } else {
+        SigStatus::Mismatch(digest)
+    };
+    SigCheck {
+        pkg: pkg.to_string(),
+        status,
+    }
+}
+
+pub fn hex(digest: &Digest) -> String {
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn parse_hex(s: &str) -> Option<Digest> {
+    let s: Vec<u8> = s.bytes().filter(|&b| b != b':').collect();
+    // from_str_radix would take a sign too
+    if s.len() != 64 || !s.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    let mut digest = [0; 32];
+    for (i, pair) in s.chunks(2).enumerate() {
+        digest[i] = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn parses_hex_digests() {
+        let digest = parse_hex(SHA).unwrap();
+        assert_eq!(digest[..2], [0x01, 0x23]);
+        assert_eq!(hex(&digest), SHA);
+        // keytool prints them colon separated and upper case
+        let keytool: Vec<String> = SHA
+            .as_bytes()
+            .chunks(2)
+            .map(|b| String::from_utf8_lossy(b).to_uppercase())
+            .collect();
+        assert_eq!(parse_hex(&keytool.join(":")), Some(digest));
+        assert_eq!(parse_hex(&SHA[2..]), None);
+        assert_eq!(parse_hex(&format!("{SHA}00")), None);
+        assert_eq!(parse_hex(&SHA.replace('0', "g")), None);
+        assert_eq!(parse_hex(&SHA.replacen("01", "+1", 1)), None);
+    }
+
+    #[test]
+    fn parses_the_allow_list() {
+        let txt = format!(
+            "# upstream\n\ncom.app1 {SHA}\n  com.app1\t{}  \norg.xxx2 {SHA}\n",
+            SHA.replace('0', "f")
+        );
+        let allow = AllowList::parse(&txt).unwrap();
+        assert_eq!(
+            allow.packages().collect::<Vec<_>>(),
+            ["com.app1", "org.xxx2"]
+        );
+        assert_eq!(allow.digests("com.app1").count(), 2);
+        assert_eq!(allow.digests("com.app3").count(), 0);
+
+        let err = AllowList::parse(&format!("com.app1 {SHA}\ncom.app2")).err();
+        assert_eq!(
+            err.as_deref(),
+            Some("line 2: expected '<package> <sha256>'")
+        );
+        let err = AllowList::parse("com.app1 abcd").err();
+        assert_eq!(err.as_deref(), Some("line 1: invalid sha256 digest"));
+    }
+
+    #[test]
+    fn checks_against_the_allow_list() {
+        let other = SHA.replace('0', "f");
+        let allow = AllowList::parse(&format!("com.app1 {SHA}\ncom.app1 {other}\n")).unwrap();
+        let digest = |hex: &str| Ok(parse_hex(hex).unwrap());
+        let status = |pkg, d| check(pkg, d, &allow).status;
+
+        assert!(matches!(status("com.app1", digest(SHA)), SigStatus::Match));
+        assert!(matches!(
+            status("com.app1", digest(&other)),
+            SigStatus::Match
+        ));
+        let resigned = SHA.replace('1', "e");
+        assert!(matches!(
+            status("com.app1", digest(&resigned)),
+            SigStatus::Mismatch(d) if hex(&d) == resigned
+        ));
+        assert!(matches!(
+            status("org.xxx2", digest(SHA)),
+            SigStatus::Unlisted
+        ));
+        assert!(matches!(
+            status("com.app1", Err(io::Error::other("no v1, v2 or v3 signature"))),
+            SigStatus::Unreadable(e) if e == "no v1, v2 or v3 signature"
+        ));
+
+        let missing = inspect("com.app1", Path::new("/nonexistent/base.apk"), &allow);
+        assert!(matches!(missing.status, SigStatus::Unreadable(_)));
+        assert!(!missing.is_candidate());
+    }
+
+    #[test]
+    fn shows_the_mismatched_digest() {
+        let check = SigCheck {
+            pkg: "com.app1".to_string(),
+            status: SigStatus::Mismatch(parse_hex(SHA).unwrap()),
+        };
+        assert!(check.is_candidate());
+        assert_eq!(
+            check.to_string(),
+            format!("com.app1: signature {SHA} is not in the allow-list")
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use crate::config::Config;
+
+pub struct Store {
+    /// what the `stores` config key takes
+    pub name: &'static str,
+    pub label: &'static str,
+    pub package: &'static str,
+    /// process name prefixes, `com.android.vending` also covers `com.android.vending:background`
+    pub processes: &'static [&'static str],
+}
+
+/// Stores the module knows, keep in sync with `STORES` in zygisk/jni/module.cpp
+pub const STORES: [Store; 3] = [
+    Store {
+        name: "play",
+        label: "Google Play Store",
+        package: "com.android.vending",
+        processes: &["com.android.vending"],
+    },
+    Store {
+        name: "galaxy",
+        label: "Galaxy Store",
+        package: "com.sec.android.app.samsungapps",
+        processes: &["com.sec.android.app.samsungapps"],
+    },
+    Store {
+        name: "appgallery",
+        label: "Huawei AppGallery",
+        package: "com.huawei.appmarket",
+        processes: &["com.huawei.appmarket"],
+    },
+];
+
+pub fn by_name(name: &str) -> Option<&'static Store> {
+    STORES.iter().find(|s| s.name == name)
+}
+
+/// Stores listed in `stores = play, galaxy` of the config, the Play Store if unset
+pub fn targets(config: &Config) -> Result<Vec<&'static Store>, String> {
+    let Some(names) = config.get("stores") else {
+        return Ok(vec![&STORES[0]]);
+    };
+    let mut targets = Vec::new();
+    for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        let Some(store) = by_name(name) else {
+            let known: Vec<&str> = STORES.iter().map(|s| s.name).collect();
+            return Err(format!(
+                "Unknown store '{name}', expected some of {}",
+                known.join(", ")
+            ));
+        };
+        if !targets.iter().any(|t: &&Store| t.name == store.name) {
+            targets.push(store);
+        }
+    }
+    if targets.is_empty() {
+        return Err("'stores' lists no store".to_string());
+    }
+    Ok(targets)
+}
@@ -0,0 +1,74 @@
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::tui;
+
+const STDOUT: i32 = 1;
+const TCSANOW: i32 = 0;
+const SIGINT: i32 = 2;
+const SIGHUP: i32 = 1;
+const SIGTERM: i32 = 15;
+const SIG_DFL: usize = 0;
+
+unsafe extern "C" {
+    fn tcgetattr(fd: i32, termios: *mut u8) -> i32;
+    fn tcsetattr(fd: i32, action: i32, termios: *const u8) -> i32;
+    fn write(fd: i32, buf: *const u8, count: usize) -> isize;
+    fn signal(sig: i32, handler: usize) -> usize;
+    fn raise(sig: i32) -> i32;
+}
+
+/// `struct termios` kept opaque, large enough for glibc's and bionic's
+#[repr(C, align(8))]
+struct Termios(UnsafeCell<[u8; 256]>);
+unsafe impl Sync for Termios {}
+
+static ORIG: Termios = Termios(UnsafeCell::new([0; 256]));
+static SAVED: AtomicBool = AtomicBool::new(false);
+
+/// Remembers the cooked mode of the terminal before `Menus` switches to raw mode,
+/// `restore` brings it back on panics, termination signals and `fatal`
+pub fn save() {
+    if SAVED.load(Ordering::Acquire) {
+        return;
+    }
+    if unsafe { tcgetattr(STDOUT, ORIG.0.get().cast()) } != 0 {
+        return;
+    }
+    SAVED.store(true, Ordering::Release);
+    // Termux sends SIGHUP when the session is closed
+    for sig in [SIGINT, SIGHUP, SIGTERM] {
+        unsafe { signal(sig, on_signal as extern "C" fn(i32) as usize) };
+    }
+}
+
+/// Cooked mode, main screen and a visible cursor.
+/// Only async-signal-safe calls, it runs in the signal handler.
+pub fn restore() {
+    if !SAVED.load(Ordering::Acquire) {
+        return;
+    }
+    let mut seq: &[u8] = b"\x1b[?25h";
+    if tui::IN_ALT_SCREEN.load(Ordering::Relaxed) {
+        seq = b"\x1b[?1049l\x1b[?25h";
+    }
+    unsafe {
+        tcsetattr(STDOUT, TCSANOW, ORIG.0.get().cast());
+        write(STDOUT, seq.as_ptr(), seq.len());
+    }
+}
+
+extern "C" fn on_signal(sig: i32) {
+    restore();
+    unsafe {
+        signal(sig, SIG_DFL);
+        raise(sig);
+    }
+}
+
+/// Exits with `msg` after restoring the terminal, `process::exit` skips the `RawTerminal` drop
+pub fn fatal(msg: impl std::fmt::Display) -> ! {
+    restore();
+    eprintln!("{msg}");
+    std::process::exit(1)
+}
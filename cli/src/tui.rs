@@ -0,0 +1,649 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use termion::event::Key;
+use termion::{clear, cursor, screen};
+
+use crate::colorize::ToColored;
+use crate::line_edit::LineEdit;
+use crate::menus::{self, KeySource, Menus};
+use crate::{IOResult, MODULE_DETACH};
+
+/// Set while the alternate screen is shown, so the panic hook can leave it
+pub static IN_ALT_SCREEN: AtomicBool = AtomicBool::new(false);
+static RESIZED: AtomicBool = AtomicBool::new(false);
+
+const SIGWINCH: i32 = 28;
+
+unsafe extern "C" {
+    fn signal(sig: i32, handler: usize) -> usize;
+}
+
+extern "C" fn on_winch(_: i32) {
+    RESIZED.store(true, Ordering::Relaxed);
+}
+
+/// Leaves the alternate screen and restores the SIGWINCH handler when dropped
+struct AltScreen<'m, W: Write, K: KeySource> {
+    menus: &'m mut Menus<W, K>,
+    prev_winch: usize,
+}
+
+impl<'m, W: Write, K: KeySource> AltScreen<'m, W, K> {
+    fn enter(menus: &'m mut Menus<W, K>) -> io::Result<Self> {
+        write!(
+            menus.stdout,
+            "{}{}",
+            screen::ToAlternateScreen,
+            cursor::Hide
+        )?;
+        menus.stdout.flush()?;
+        IN_ALT_SCREEN.store(true, Ordering::Relaxed);
+        let prev_winch = unsafe { signal(SIGWINCH, on_winch as extern "C" fn(i32) as usize) };
+        Ok(Self { menus, prev_winch })
+    }
+}
+
+impl<W: Write, K: KeySource> Drop for AltScreen<'_, W, K> {
+    fn drop(&mut self) {
+        unsafe { signal(SIGWINCH, self.prev_winch) };
+        let _ = write!(self.menus.stdout, "{}", screen::ToMainScreen);
+        let _ = self.menus.stdout.flush();
+        IN_ALT_SCREEN.store(false, Ordering::Relaxed);
+    }
+}
+
+struct Entry {
+    name: String,
+    installed: bool,
+    user_installed: bool,
+    /// detached for every user
+    detached: bool,
+    /// users it is detached for in addition
+    users: Vec<u32>,
+    /// toggled, applied with ENTER
+    pending: bool,
+}
+
+#[derive(Default)]
+struct AppInfo {
+    version: Option<String>,
+    installer: Option<String>,
+}
+
+#[cfg(target_os = "linux")]
+fn get_app_info(pkg: &str) -> AppInfo {
+    AppInfo {
+        version: Some(format!("1.0 ({})", pkg.len())),
+        installer: Some("com.android.vending".to_string()),
+    }
+}
+
+#[cfg(target_os = "android")]
+fn get_app_info(pkg: &str) -> AppInfo {
+    match std::process::Command::new("dumpsys")
+        .args(["package", pkg])
+        .output()
+    {
+        Ok(out) => parse_dumpsys(&String::from_utf8_lossy(&out.stdout)),
+        Err(_) => AppInfo::default(),
+    }
+}
+
+#[cfg_attr(target_os = "linux", allow(dead_code))]
+fn parse_dumpsys(out: &str) -> AppInfo {
+    let mut info = AppInfo::default();
+    let mut code = None;
+    for l in out.lines().map(str::trim) {
+        if let Some(v) = l.strip_prefix("versionName=") {
+            info.version.get_or_insert_with(|| v.to_string());
+        } else if let Some(v) = l.strip_prefix("versionCode=") {
+            code.get_or_insert_with(|| v.split(' ').next().unwrap_or(v).to_string());
+        } else if let Some(v) = l
+            .strip_prefix("installerPackageName=")
+            // sideloaded
+            .filter(|&v| v != "null")
+        {
+            info.installer.get_or_insert_with(|| v.to_string());
+        }
+    }
+    if let (Some(v), Some(code)) = (&mut info.version, code) {
+        v.push_str(&format!(" ({code})"));
+    }
+    info
+}
+
+/// `YYYY-MM-DD HH:MM UTC` of a unix time
+fn format_date(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let (h, m) = (secs % 86400 / 3600, secs % 3600 / 60);
+    // civil from days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let mo = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + (mo <= 2) as i64;
+    format!("{y:04}-{mo:02}-{d:02} {h:02}:{m:02} UTC")
+}
+
+struct AppManager {
+    apps: Vec<Entry>,
+    /// indices into `apps` matching the filter, with the matched positions
+    view: Vec<(usize, Vec<usize>)>,
+    filter: LineEdit,
+    searching: bool,
+    select_idx: usize,
+    top: usize,
+    status: String,
+    infos: HashMap<String, AppInfo>,
+    quit_armed: bool,
+    /// the next frame starts from a blank screen, after a resize
+    needs_clear: bool,
+}
+
+impl AppManager {
+    fn new(installed_apps: &[u8]) -> Self {
+        let mut apps: Vec<Entry> = crate::parse_installed_apps(installed_apps)
+            .iter()
+            .map(|app| Entry {
+                name: app.name.to_string(),
+                installed: true,
+                user_installed: app.is_user_installed(),
+                detached: false,
+                users: Vec::new(),
+                pending: false,
+            })
+            .collect();
+        apps.sort_by_key(|e| !e.user_installed);
+        let mut s = Self {
+            apps,
+            view: Vec::new(),
+            filter: LineEdit::default(),
+            searching: false,
+            select_idx: 0,
+            top: 0,
+            status: String::new(),
+            infos: HashMap::new(),
+            quit_armed: false,
+            needs_clear: true,
+        };
+        s.load_detached();
+        s.refilter();
+        s
+    }
+
+    fn load_detached(&mut self) {
+        for e in &mut self.apps {
+            e.detached = false;
+            e.users.clear();
+        }
+        let content = fs::read(MODULE_DETACH).unwrap_or_default();
+        for d in crate::get_detached_apps(&content) {
+            let i = match self.apps.iter().position(|e| e.name == d.name) {
+                Some(i) => i,
+                None => {
+                    // detached but not installed, still listed so it can be re-attached
+                    self.apps.push(Entry {
+                        name: d.name,
+                        installed: false,
+                        user_installed: false,
+                        detached: false,
+                        users: Vec::new(),
+                        pending: false,
+                    });
+                    self.apps.len() - 1
+                }
+            };
+            match d.user {
+                Some(user) => self.apps[i].users.push(user),
+                None => self.apps[i].detached = true,
+            }
+        }
+    }
+
+    fn refilter(&mut self) {
+        let selected = self.selected().map(|e| e.name.clone());
+        let query = self.filter.text().trim();
+        self.view = if query.is_empty() {
+            (0..self.apps.len()).map(|i| (i, Vec::new())).collect()
+        } else {
+            let mut matches: Vec<_> = self
+                .apps
+                .iter()
+                .enumerate()
+                .filter_map(|(i, e)| Some((crate::fuzzy::fuzzy_match(query, &e.name)?, i)))
+                .collect();
+            matches.sort_by_key(|(m, _)| std::cmp::Reverse(m.score));
+            matches.into_iter().map(|(m, i)| (i, m.positions)).collect()
+        };
+        self.select_idx = selected
+            .and_then(|name| {
+                self.view
+                    .iter()
+                    .position(|&(i, _)| self.apps[i].name == name)
+            })
+            .unwrap_or(0);
+    }
+
+    fn selected(&self) -> Option<&Entry> {
+        self.view.get(self.select_idx).map(|&(i, _)| &self.apps[i])
+    }
+
+    fn pending(&self) -> usize {
+        self.apps.iter().filter(|e| e.pending).count()
+    }
+
+    fn apply(&mut self) -> IOResult<()> {
+        let to_detach: Vec<&str> = self
+            .apps
+            .iter()
+            .filter(|e| e.pending && !e.detached)
+            .map(|e| e.name.as_str())
+            .collect();
+        let to_reattach: Vec<&str> = self
+            .apps
+            .iter()
+            .filter(|e| e.pending && e.detached)
+            .map(|e| e.name.as_str())
+            .collect();
+        if to_detach.is_empty() && to_reattach.is_empty() {
+            self.status = "Nothing to apply, SPACE marks an app".to_string();
+            return Ok(());
+        }
+        let detached = crate::write_detached(&to_detach, None)?.len();
+        let reattached = if to_reattach.is_empty() {
+            0
+        } else {
+            crate::remove_detached(&to_reattach, None)?.len()
+        };
+        let stops = crate::detach_bin_changed(None);
+        for e in &mut self.apps {
+            e.pending = false;
+        }
+        self.load_detached();
+        let applied = match stops {
+            Ok(stops) => match stops.iter().find(|s| !s.applied()) {
+                None => "Changes are applied!".to_string(),
+                Some(stop) => stop.to_string(),
+            },
+            Err(e) => format!("Could not stop the store: {e}"),
+        };
+        self.status = format!("Detached {detached}, re-attached {reattached}. {applied}");
+        Ok(())
+    }
+
+    /// Handles a key, `true` to leave
+    fn key(&mut self, key: Key, page: usize) -> IOResult<bool> {
+        let armed = std::mem::take(&mut self.quit_armed);
+        self.status.clear();
+        if self.searching {
+            match key {
+                Key::Char('\n') | Key::Down => self.searching = false,
+                Key::Esc => {
+                    self.searching = false;
+                    self.filter = LineEdit::default();
+                    self.refilter();
+                }
+                Key::Ctrl('c') => return Ok(true),
+                k => {
+                    let query = self.filter.text().to_string();
+                    if self.filter.edit(k) && self.filter.text() != query {
+                        self.refilter();
+                        self.select_idx = 0;
+                    }
+                }
+            }
+            return Ok(false);
+        }
+        if let Some(i) = menus::navigate(key, self.select_idx, self.view.len(), page) {
+            self.select_idx = i;
+            return Ok(false);
+        }
+        match key {
+            Key::Char('/') => self.searching = true,
+            Key::Char(' ') => {
+                if let Some(&(i, _)) = self.view.get(self.select_idx) {
+                    self.apps[i].pending ^= true;
+                    self.select_idx = (self.select_idx + 1).min(self.view.len() - 1);
+                }
+            }
+            Key::Char('\n') => self.apply()?,
+            Key::Ctrl('c') => return Ok(true),
+            Key::Char('q') | Key::Esc => {
+                let pending = self.pending();
+                if pending == 0 || armed {
+                    return Ok(true);
+                }
+                self.quit_armed = true;
+                self.status = format!("{pending} unapplied change(s), press q again to discard");
+            }
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    fn draw(&mut self, out: &mut impl Write, (cols, rows): (u16, u16)) -> io::Result<()> {
+        let (cols, rows) = (cols as usize, (rows as usize).max(4));
+        // status bar, search line and the footer take a row each
+        let height = rows - 3;
+        let list_w = if cols >= 60 { cols * 3 / 5 } else { cols };
+        self.top = menus::scroll(self.top, self.select_idx, height);
+        // every row is overwritten and cleared past its end, a full clear only after a resize
+        if std::mem::take(&mut self.needs_clear) {
+            write!(out, "{}", clear::All)?;
+        }
+        write!(out, "{}", cursor::Goto(1, 1))?;
+
+        let detached = self
+            .apps
+            .iter()
+            .filter(|e| e.detached || !e.users.is_empty())
+            .count();
+        let bar = format!(
+            " zygisk-detach │ {} apps │ {detached} detached │ {} pending",
+            self.apps.len(),
+            self.pending()
+        );
+        let bar = format!("{:<cols$}", menus::truncate(&bar, cols));
+        write!(out, "{}", bar.selected())?;
+
+        write!(out, "{}", cursor::Goto(1, 2))?;
+        if self.searching || !self.filter.text().is_empty() {
+            let line = format!("/{}", self.filter.text());
+            write!(out, "{}", menus::truncate(&line, cols))?;
+        } else {
+            write!(out, "{}", "/ to search".faint())?;
+        }
+        write!(out, "{}", clear::UntilNewline)?;
+
+        let details = if list_w < cols {
+            self.details()
+        } else {
+            Vec::new()
+        };
+        for row in 0..height {
+            write!(out, "{}", cursor::Goto(1, row as u16 + 3))?;
+            if let Some((i, positions)) = self.view.get(self.top + row) {
+                let e = &self.apps[*i];
+                let mark = match (e.pending, e.detached) {
+                    (true, false) => "+".success().to_string(),
+                    (true, true) => "-".danger().to_string(),
+                    (false, true) => "●".success().to_string(),
+                    (false, false) if !e.users.is_empty() => "◐".warning().to_string(),
+                    (false, false) => " ".to_string(),
+                };
+                let name = crate::fuzzy::Highlighted {
+                    text: &e.name,
+                    positions: positions.clone(),
+                }
+                .to_string();
+                let name = menus::truncate(&name, list_w.saturating_sub(3).max(1));
+                write!(out, "{mark} ")?;
+                if self.top + row == self.select_idx && !self.searching {
+                    write!(out, "{}", name.selected())?;
+                } else if !e.installed {
+                    write!(out, "{}", name.faint())?;
+                } else {
+                    write!(out, "{name}")?;
+                }
+            } else if row == 0 && self.view.is_empty() {
+                write!(out, "{}", "no matches".faint())?;
+            }
+            write!(out, "{}", clear::UntilNewline)?;
+
+            if list_w < cols {
+                let x = list_w as u16 + 1;
+                write!(out, "{}{}", cursor::Goto(x, row as u16 + 3), "│".faint())?;
+                if let Some(line) = details.get(row) {
+                    let detail_w = cols - list_w - 2;
+                    write!(
+                        out,
+                        "{}{}",
+                        cursor::Goto(x + 2, row as u16 + 3),
+                        menus::truncate(line, detail_w.max(1))
+                    )?;
+                }
+            }
+        }
+
+        let hints = if self.searching {
+            "type to filter, ENTER/↓ to the list, ESC clear".to_string()
+        } else {
+            format!(
+                "{}  ↑/↓ move  SPACE mark  ENTER apply  / search  q quit",
+                menus::position(self.select_idx, self.view.len())
+            )
+        };
+        write!(out, "{}", cursor::Goto(1, rows as u16))?;
+        if self.status.is_empty() {
+            write!(out, "{}", menus::truncate(&hints, cols).faint())?;
+        } else {
+            write!(out, "{}", menus::truncate(&self.status, cols).warning())?;
+        }
+        write!(out, "{}", clear::UntilNewline)?;
+        if self.searching {
+            let x = (self.filter.cursor_width() + 2).min(cols) as u16;
+            write!(out, "{}{}", cursor::Goto(x, 2), cursor::Show)?;
+        } else {
+            write!(out, "{}", cursor::Hide)?;
+        }
+        out.flush()
+    }
+
+    fn details(&self) -> Vec<String> {
+        let Some(e) = self.selected() else {
+            return Vec::new();
+        };
+        let mut lines = vec![e.name.clone(), String::new()];
+        let unknown = || "…".faint().to_string();
+        if e.installed {
+            let info = self.infos.get(&e.name);
+            let field = |f: fn(&AppInfo) -> &Option<String>| match info {
+                Some(info) => f(info).clone().unwrap_or_else(|| "-".to_string()),
+                None => unknown(),
+            };
+            lines.push(format!("Version:   {}", field(|i| &i.version)));
+            lines.push(format!("Installer: {}", field(|i| &i.installer)));
+            let kind = if e.user_installed { "user" } else { "system" };
+            lines.push(format!("Type:      {kind}"));
+        } else {
+            lines.push("Not installed".faint().to_string());
+        }
+        lines.push(String::new());
+        if e.detached {
+            let date = crate::get_detach_date(&e.name, None).map_or("-".to_string(), format_date);
+            lines.push(format!("{} for every user", "Detached".success()));
+            lines.push(format!("  since {date}"));
+        }
+        for &user in &e.users {
+            let date =
+                crate::get_detach_date(&e.name, Some(user)).map_or("-".to_string(), format_date);
+            lines.push(format!("{} for user {user}", "Detached".warning()));
+            lines.push(format!("  since {date}"));
+        }
+        if !e.detached && e.users.is_empty() {
+            lines.push("Attached".faint().to_string());
+        }
+        if e.pending {
+            let action = if e.detached { "re-attach" } else { "detach" };
+            lines.push(String::new());
+            lines.push(format!("Pending: {action}"));
+        }
+        lines
+    }
+
+    /// Fetches the details of the highlighted app, `true` if there was anything to fetch
+    fn fetch_info(&mut self) -> bool {
+        let Some(e) = self.selected() else {
+            return false;
+        };
+        if !e.installed || self.infos.contains_key(&e.name) {
+            return false;
+        }
+        let name = e.name.clone();
+        let info = get_app_info(&name);
+        self.infos.insert(name, info);
+        true
+    }
+}
+
+/// Full-screen app manager on the alternate screen, detach and re-attach in one list
+pub fn app_manager<W: Write, K: KeySource>(
+    menus: &mut Menus<W, K>,
+    installed_apps: &[u8],
+) -> IOResult<()> {
+    let mut mgr = AppManager::new(installed_apps);
+    let screen = AltScreen::enter(menus)?;
+    let menus = &mut *screen.menus;
+    loop {
+        let size = menus.size();
+        mgr.draw(&mut menus.stdout, size)?;
+        // details are looked up while idle so holding a key down stays smooth
+        if !menus.keys.has_pending() && mgr.fetch_info() {
+            mgr.draw(&mut menus.stdout, size)?;
+        }
+        let Some(keys) = menus.keys.poll_keys(-1)? else {
+            return Ok(());
+        };
+        if RESIZED.swap(false, Ordering::Relaxed) {
+            mgr.needs_clear = true;
+        } else if keys.is_empty() {
+            continue;
+        }
+        let (_, rows) = menus.size();
+        let page = (rows as usize).saturating_sub(3).max(1);
+        for key in keys {
+            if mgr.key(key, page)? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vt;
+
+    const INSTALLED: &[u8] = b"\
+package:/data/app/~~a==/com.app1-b==/base.apk=com.app1
+package:/data/app/~~c==/org.xxx2-d==/base.apk=org.xxx2
+package:/system/app/Tool/Tool.apk=com.tool
+";
+
+    /// The app manager after `keys`, 64x10 so the details pane is shown
+    fn app_manager_after(keys: Vec<Key>) -> String {
+        let mut menus = Menus::with_io(vt::Screen::new(64, 10), keys.into_iter(), Some((64, 10)));
+        app_manager(&mut menus, INSTALLED).unwrap();
+        menus.stdout.text()
+    }
+
+    #[test]
+    fn app_manager_navigation() {
+        let _dir = crate::tests::scratch_dir();
+        assert_eq!(
+            app_manager_after(vec![Key::Down, Key::Down, Key::Down]),
+            " zygisk-detach │ 3 apps │ 0 detached │ 0 pending
+/ to search
+  com.app1                            │ com.tool
+  org.xxx2                            │
+  com.tool                            │ Version:   1.0 (8)
+                                      │ Installer: com.android.…
+                                      │ Type:      system
+                                      │
+                                      │ Attached
+3/3  ↑/↓ move  SPACE mark  ENTER apply  / search  q quit"
+        );
+    }
+
+    #[test]
+    fn app_manager_filter() {
+        let _dir = crate::tests::scratch_dir();
+        let keys = vec![Key::Char('/'), Key::Char('x'), Key::Char('x')];
+        assert_eq!(
+            app_manager_after(keys),
+            " zygisk-detach │ 3 apps │ 0 detached │ 0 pending
+/xx
+  org.xxx2                            │ org.xxx2
+                                      │
+                                      │ Version:   1.0 (8)
+                                      │ Installer: com.android.…
+                                      │ Type:      user
+                                      │
+                                      │ Attached
+type to filter, ENTER/↓ to the list, ESC clear"
+        );
+        // ESC drops the filter
+        let keys = vec![Key::Char('/'), Key::Char('x'), Key::Esc];
+        assert!(app_manager_after(keys).contains("  com.tool"));
+    }
+
+    #[test]
+    fn app_manager_detaches_marked() {
+        let _dir = crate::tests::scratch_dir();
+        assert_eq!(
+            app_manager_after(vec![Key::Char(' '), Key::Char('\n')]),
+            " zygisk-detach │ 3 apps │ 1 detached │ 0 pending
+/ to search
+● com.app1                            │ org.xxx2
+  org.xxx2                            │
+  com.tool                            │ Version:   1.0 (8)
+                                      │ Installer: com.android.…
+                                      │ Type:      user
+                                      │
+                                      │ Attached
+Detached 1, re-attached 0. Changes are applied!"
+        );
+        assert_eq!(crate::get_detached_names().unwrap(), ["com.app1"]);
+    }
+
+    #[test]
+    fn app_manager_warns_about_unapplied_changes() {
+        let _dir = crate::tests::scratch_dir();
+        let screen = app_manager_after(vec![Key::Char(' '), Key::Char('q')]);
+        assert!(screen.contains("\n+ com.app1"));
+        assert!(screen.ends_with("1 unapplied change(s), press q again to discard"));
+        // the second q leaves, the remaining keys are not read
+        let keys = vec![
+            Key::Char(' '),
+            Key::Char('q'),
+            Key::Char('q'),
+            Key::Char('\n'),
+        ];
+        app_manager_after(keys);
+        assert!(crate::get_detached_names().unwrap().is_empty());
+    }
+
+    #[test]
+    fn parses_dumpsys_package() {
+        let out = "\
+Packages:
+  Package [com.app1] (5a1b2c3):
+    userId=10150
+    pkg=Package{9d8e7f6 com.app1}
+    versionCode=4213 minSdk=26 targetSdk=34
+    versionName=2.1.3
+    installerPackageName=com.android.vending
+  Hidden system packages:
+  Package [com.app1] (0f0e0d0):
+    versionCode=100 minSdk=26 targetSdk=33
+    versionName=1.0
+";
+        let info = parse_dumpsys(out);
+        assert_eq!(info.version.as_deref(), Some("2.1.3 (4213)"));
+        assert_eq!(info.installer.as_deref(), Some("com.android.vending"));
+
+        let info = parse_dumpsys("    versionCode=7 minSdk=21\n");
+        assert_eq!(info.version, None);
+        assert_eq!(info.installer, None);
+        let info = parse_dumpsys("    versionName=3\n    installerPackageName=null\n");
+        assert_eq!(info.version.as_deref(), Some("3"));
+        assert_eq!(info.installer, None);
+    }
+}
@@ -0,0 +1,132 @@
+//! Minimal terminal emulator for the menu tests, applies the escape sequences
+//! the menus emit to a grid of cells. Colors and styles are dropped.
+
+use std::io::{self, Write};
+
+/// Output only reaches the screen on `flush`, like the `BufWriter` the menus
+/// write to, so the screen is what the user sees while a menu waits for a key
+pub struct Screen {
+    cols: usize,
+    rows: usize,
+    cells: Vec<Vec<char>>,
+    pub cursor: (usize, usize),
+    pending: Vec<u8>,
+}
+
+impl Write for Screen {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let pending = std::mem::take(&mut self.pending);
+        self.feed(&pending);
+        Ok(())
+    }
+}
+
+impl Screen {
+    pub fn new(cols: u16, rows: u16) -> Self {
+        let (cols, rows) = (cols as usize, rows as usize);
+        Self {
+            cols,
+            rows,
+            cells: vec![vec![' '; cols]; rows],
+            cursor: (0, 0),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Feeds raw mode output, `\n` only moves down
+    fn feed(&mut self, out: &[u8]) {
+        let out = String::from_utf8_lossy(out);
+        let mut chars = out.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\x1b' if chars.peek() == Some(&'[') => {
+                    chars.next();
+                    let mut params = String::new();
+                    let mut fin = None;
+                    for p in chars.by_ref() {
+                        if ('@'..='~').contains(&p) {
+                            fin = Some(p);
+                            break;
+                        }
+                        params.push(p);
+                    }
+                    if let Some(fin) = fin {
+                        self.csi(&params, fin);
+                    }
+                }
+                '\r' => self.cursor.0 = 0,
+                '\n' => self.line_feed(),
+                c if c.is_control() => {}
+                c => {
+                    if self.cursor.0 >= self.cols {
+                        self.cursor.0 = 0;
+                        self.line_feed();
+                    }
+                    let (x, y) = self.cursor;
+                    self.cells[y][x] = c;
+                    self.cursor.0 += 1;
+                }
+            }
+        }
+    }
+
+    fn line_feed(&mut self) {
+        if self.cursor.1 + 1 < self.rows {
+            self.cursor.1 += 1;
+        } else {
+            self.cells.remove(0);
+            self.cells.push(vec![' '; self.cols]);
+        }
+    }
+
+    fn csi(&mut self, params: &str, fin: char) {
+        if params.starts_with('?') {
+            // cursor visibility and the alternate screen
+            return;
+        }
+        let nums: Vec<usize> = params.split(';').map(|n| n.parse().unwrap_or(0)).collect();
+        let n = nums.first().copied().filter(|&n| n > 0).unwrap_or(1);
+        let (x, y) = self.cursor;
+        match fin {
+            'A' => self.cursor.1 = y.saturating_sub(n),
+            'B' => self.cursor.1 = (y + n).min(self.rows - 1),
+            'C' => self.cursor.0 = (x + n).min(self.cols - 1),
+            'D' => self.cursor.0 = x.saturating_sub(n),
+            'H' => {
+                let col = nums.get(1).copied().filter(|&n| n > 0).unwrap_or(1);
+                self.cursor = ((col - 1).min(self.cols - 1), (n - 1).min(self.rows - 1));
+            }
+            'J' => match nums[0] {
+                2 => self.cells.iter_mut().for_each(|r| r.fill(' ')),
+                _ => {
+                    self.cells[y][x.min(self.cols)..].fill(' ');
+                    self.cells[y + 1..].iter_mut().for_each(|r| r.fill(' '));
+                }
+            },
+            'K' => match nums[0] {
+                2 => self.cells[y].fill(' '),
+                _ => self.cells[y][x.min(self.cols)..].fill(' '),
+            },
+            _ => {}
+        }
+    }
+
+    /// The screen as text, trailing blanks and empty rows trimmed
+    pub fn text(&self) -> String {
+        let lines: Vec<String> = self
+            .cells
+            .iter()
+            .map(|r| r.iter().collect::<String>().trim_end().to_string())
+            .collect();
+        let used = lines
+            .iter()
+            .rposition(|l| !l.is_empty())
+            .map_or(0, |i| i + 1);
+        lines[..used].join("\n")
+    }
+}
@@ -1,5 +1,13 @@
 #!/system/bin/sh
 
+MODDIR=${0%/*}
+
+# the stores targeted in the config may have changed since the last boot
+"$MODDIR"/detach stores sync >/dev/null 2>&1
+
 if magisk --denylist status; then
-	magisk --denylist rm com.android.vending
+	STORES=$("$MODDIR"/detach stores --packages 2>/dev/null) || STORES=com.android.vending
+	for store in $STORES; do
+		magisk --denylist rm "$store"
+	done
 fi
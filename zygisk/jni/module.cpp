@@ -15,6 +15,15 @@
 
 static uint8_t* DETACH_TXT;
 static uint8_t HEADERS_LEN;
+static uint32_t GET_PACKAGE_INFO_CODE;
+static uint32_t USER_ID;
+
+// keep in sync with STORES in cli/src/stores.rs, the first one is the default target
+static const char* const STORES[] = {
+    "com.android.vending",
+    "com.sec.android.app.samsungapps",
+    "com.huawei.appmarket",
+};
 
 struct PParcel {
     size_t error;
@@ -28,7 +37,7 @@ static inline void detach(PParcel* parcel, uint32_t code) {
     uint32_t pkg_len = p.readInt32();
     uint32_t pkg_len_b = pkg_len * 2 - 1;
     if (pkg_len_b > UINT8_MAX) return;
-    if (code == getPackageInfo_code) return;
+    if (code == GET_PACKAGE_INFO_CODE) return;
     auto pkg_ptr = p.readString16(pkg_len);
 
     size_t i = 0;
@@ -36,6 +45,15 @@ static inline void detach(PParcel* parcel, uint32_t code) {
     while ((dlen = DETACH_TXT[i])) {
         uint8_t* dptr = DETACH_TXT + i + sizeof(dlen);
         i += sizeof(dlen) + dlen;
+        // even length: tagged record, pkg_len_b is always odd
+        if (!(dlen & 1)) {
+            uint32_t user;
+            if (dptr[0] != TAG_USER_APP || dlen <= 1 + sizeof(user)) continue;
+            memcpy(&user, dptr + 1, sizeof(user));
+            if (user != USER_ID) continue;
+            dptr += 1 + sizeof(user);
+            dlen -= 1 + sizeof(user);
+        }
         if (dlen != pkg_len_b)
             continue;
         if (!memcmp(dptr, pkg_ptr, dlen)) {
@@ -45,6 +63,39 @@ static inline void detach(PParcel* parcel, uint32_t code) {
     }
 }
 
+// whether `store` is targeted by a TAG_STORE record, the default store if there are none
+static bool is_target(const char* store) {
+    size_t store_len = strlen(store);
+    bool any = false;
+    size_t i = 0;
+    uint8_t dlen;
+    while ((dlen = DETACH_TXT[i])) {
+        uint8_t* dptr = DETACH_TXT + i + sizeof(dlen);
+        i += sizeof(dlen) + dlen;
+        if ((dlen & 1) || dptr[0] != TAG_STORE) continue;
+        any = true;
+        // same encoding as the app names, every byte followed by a 0 but the last
+        if ((size_t)(dlen - 1) != store_len * 2 - 1) continue;
+        size_t j = 0;
+        for (; j < store_len; j++) {
+            if (dptr[1 + j * 2] != (uint8_t)store[j]) break;
+            if (j + 1 < store_len && dptr[2 + j * 2] != 0) break;
+        }
+        if (j == store_len) return true;
+    }
+    return !any && store == STORES[0];
+}
+
+// the codes of the newest SDK of PM_CODES not after `sdk`, the oldest one for older SDKs.
+// SDKs without a file in aidl/ (32, 35 and later) take the codes of the one before, vendor
+// their IPackageManager.aidl and re-run `detach aidl-gen` if those codes turn out different.
+static const PmCodes* pm_codes(int sdk) {
+    const PmCodes* codes = &PM_CODES[0];
+    for (size_t i = 0; i < ARR_LEN(PM_CODES); i++)
+        if ((int)PM_CODES[i].sdk <= sdk) codes = &PM_CODES[i];
+    return codes;
+}
+
 int (*transact_orig)(void*, int32_t, uint32_t, void*, void*, uint32_t);
 
 int transact_hook(void* self, int32_t handle, uint32_t code, void* pdata, void* preply, uint32_t flags) {
@@ -67,14 +118,20 @@ class ZygiskDetach : public zygisk::ModuleBase {
 
     void preAppSpecialize(zygisk::AppSpecializeArgs* args) override {
         const char* process = env->GetStringUTFChars(args->nice_name, nullptr);
-#define vending_pkg "com.android.vending"
-        if (memcmp(process, vending_pkg, ARR_LEN(vending_pkg))) {
-            env->ReleaseStringUTFChars(args->nice_name, process);
+        const char* store = nullptr;
+        for (size_t i = 0; i < ARR_LEN(STORES); i++) {
+            if (!strcmp(process, STORES[i])) {
+                store = STORES[i];
+                break;
+            }
+        }
+        env->ReleaseStringUTFChars(args->nice_name, process);
+        if (!store) {
             api->setOption(zygisk::Option::DLCLOSE_MODULE_LIBRARY);
             return;
         }
-        env->ReleaseStringUTFChars(args->nice_name, process);
         api->setOption(zygisk::FORCE_DENYLIST_UNMOUNT);
+        USER_ID = (uint32_t)args->uid / AID_USER_OFFSET;
 
         int fd = api->connectCompanion();
         size_t detach_len = this->read_companion(fd);
@@ -83,15 +140,23 @@ class ZygiskDetach : public zygisk::ModuleBase {
             api->setOption(zygisk::Option::DLCLOSE_MODULE_LIBRARY);
             return;
         }
+        if (!is_target(store)) {
+            LOGD("%s is not a target store", store);
+            free(DETACH_TXT);
+            api->setOption(zygisk::Option::DLCLOSE_MODULE_LIBRARY);
+            return;
+        }
         char sdk_str[2];
         if (__system_property_get("ro.build.version.sdk", sdk_str)) {
             int sdk = atoi(sdk_str);
             if (sdk >= 30) HEADERS_LEN = 3 * sizeof(uint32_t);
             else if (sdk == 29) HEADERS_LEN = 2 * sizeof(uint32_t);
             else HEADERS_LEN = 1 * sizeof(uint32_t);
+            GET_PACKAGE_INFO_CODE = pm_codes(sdk)->getPackageInfo;
         } else {
             LOGD("WARN: could not get sdk version (fallback=3)");
             HEADERS_LEN = 3 * sizeof(uint32_t);
+            GET_PACKAGE_INFO_CODE = PM_CODES[ARR_LEN(PM_CODES) - 1].getPackageInfo;
         }
 
         ino_t inode;
@@ -6,7 +6,11 @@
 #define ARR_LEN(a) (sizeof(a) / sizeof((a)[0]))
 #define STR_LEN(a) (ARR_LEN(a) - 1)
 
-#define getPackageInfo_code 3
+#include "pm_codes.hpp"
+
+#define AID_USER_OFFSET 100000
+#define TAG_USER_APP 1
+#define TAG_STORE 2
 
 struct FakeParcel {
     unsigned char* data;
@@ -0,0 +1,23 @@
+// Generated by `detach aidl-gen` from aidl/, do not edit
+
+#pragma once
+
+#include <stdint.h>
+
+struct PmCodes {
+    uint32_t sdk;
+    uint32_t isPackageAvailable;
+    uint32_t getPackageInfo;
+    uint32_t getPackageUid;
+    uint32_t getPackageGids;
+};
+
+// by SDK
+static const struct PmCodes PM_CODES[] = {
+    {28, 2, 3, 5, 6},
+    {29, 2, 3, 5, 6},
+    {30, 2, 3, 5, 6},
+    {31, 2, 3, 5, 6},
+    {33, 2, 3, 5, 6},
+    {34, 2, 3, 5, 6},
+};